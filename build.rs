@@ -1,11 +1,18 @@
 // build.rs
 
 use std::env;
+use std::ffi::OsStr;
 use std::fs;
 use std::path::Path;
 
 fn main() {
     let out_dir = env::var_os("OUT_DIR").unwrap();
+    gen_exp_table(&out_dir);
+    gen_sunrise_table(&out_dir);
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+fn gen_exp_table(out_dir: &OsStr) {
     let dest_path = Path::new(&out_dir).join("exp_table.rs");
 
     let values = (0..256).map(|n| {
@@ -16,11 +23,49 @@ fn main() {
     fs::write(
         &dest_path,
         format!(
-            "const ONE_MINUS_EXP_MINUS_X_TABLE: [u8; 256] = [{}];",
+            "progmem! {{ static ONE_MINUS_EXP_MINUS_X_TABLE: [u8; 256] = [{}]; }}",
             values.map(|n| n.to_string()).collect::<Vec<_>>().join(",\n")
         ),
     )
     .unwrap();
+}
 
-    println!("cargo:rerun-if-changed=build.rs");
+/// Generates the colors of the sunrise, going from a dim deep red to a bright warm white.
+fn gen_sunrise_table(out_dir: &OsStr) {
+    const LEN: usize = 64;
+
+    let dest_path = Path::new(&out_dir).join("sunrise_table.rs");
+
+    let values = (0..LEN).map(|n| {
+        let progress = n as f64 / (LEN - 1) as f64;
+
+        // Color of a black body going from 1000K to 3000K, using the approximation of
+        // Tanner Helland. Below 6600K, the red component is always at its maximum.
+        let temperature = (1000.0 + 2000.0 * progress) / 100.0;
+        let green = 99.4708025861 * f64::ln(temperature) - 161.1195681661;
+        let blue = if temperature <= 19.0 {
+            0.0
+        } else {
+            138.5177312231 * f64::ln(temperature - 10.0) - 305.0447927307
+        };
+
+        // The eye is much more sensitive to changes of low brightnesses, so the brightness rises
+        // slowly at first.
+        let brightness = 0.02 + 0.98 * progress * progress;
+
+        [255.0, green, blue].map(|c| (c.clamp(0.0, 255.0) * brightness).round() as u8)
+    });
+
+    fs::write(
+        &dest_path,
+        format!(
+            "progmem! {{ static SUNRISE_TABLE: [[u8; 3]; {}] = [{}]; }}",
+            LEN,
+            values
+                .map(|[r, g, b]| format!("[{}, {}, {}]", r, g, b))
+                .collect::<Vec<_>>()
+                .join(",\n")
+        ),
+    )
+    .unwrap();
 }
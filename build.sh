@@ -1 +1,2 @@
-AVR_CPU_FREQUENCY_HZ=16000000 cargo +nightly-2023-03-25 build -Z build-std-features=compiler-builtins-mem -Z build-std=core,alloc --target avr-unknown-gnu-atmega328p.json --release
+# The locations of panics are never printed, and their file names would take room in the RAM.
+AVR_CPU_FREQUENCY_HZ=16000000 RUSTFLAGS="-Z location-detail=none" cargo +nightly-2023-03-25 build -Z build-std-features=compiler-builtins-mem -Z build-std=core,alloc --target avr-unknown-gnu-atmega328p.json --release
@@ -0,0 +1,157 @@
+// Potentiometers connected to the analog inputs, used to adjust the settings.
+//
+// The raw values of the ADC are noisy, so each input is smoothed with an exponential moving
+// average, and a new value is only reported once it has moved far enough from the previously
+// reported value. Without this, the settings would constantly flicker between two neighbouring
+// values.
+
+use core::cmp;
+
+use crate::{
+    leds::Mode,
+    settings::{self, Settings},
+};
+
+/// Number of analog inputs that are sampled, starting from A0.
+#[cfg(target_arch = "avr")]
+pub const NUM_INPUTS: usize = 4;
+
+/// Parameter controlled by each analog input, starting from A0. Inputs that have no
+/// potentiometer connected must be set to `None`, otherwise their floating value would randomly
+/// change the settings, so each input must be bound explicitly once a potentiometer has been
+/// connected to it, for example with `Some(Parameter::Brightness)`.
+#[cfg(target_arch = "avr")]
+pub const BINDINGS: [Option<Parameter>; NUM_INPUTS] = [None, None, None, None];
+
+/// Minimum difference between the last reported value of an input and its new value for the
+/// new value to be reported.
+const HYSTERESIS: u8 = 3;
+
+// Parameters are only used once an input is bound to them.
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Parameter {
+    Brightness,
+    Speed,
+    FireplaceHue,
+    SegmentWidth,
+}
+
+impl Parameter {
+    /// Updates the settings given the new value of the input bound to this parameter. `mode`
+    /// is the mode currently being displayed.
+    pub fn apply(&self, settings: &mut Settings, mode: Mode, value: u8) {
+        match self {
+            Parameter::Brightness => {
+                settings.brightness = cmp::max(value, settings::MIN_BRIGHTNESS)
+            }
+            Parameter::Speed => settings.set_speed(mode, value),
+            Parameter::FireplaceHue => settings.fireplace.hue_shift = value,
+            Parameter::SegmentWidth => settings.segment_lights.segment_width = 2 + value / 16,
+        }
+    }
+}
+
+/// Smoothing of the values of an analog input.
+#[derive(Debug, Copy, Clone)]
+pub struct AnalogInput {
+    /// Moving average of the samples, multiplied by 16. `None` if no sample has been received
+    /// yet.
+    average: Option<u16>,
+    /// Value that has been reported the last time.
+    reported: u8,
+}
+
+impl AnalogInput {
+    pub const fn new() -> Self {
+        AnalogInput {
+            average: None,
+            reported: 0,
+        }
+    }
+
+    /// Feeds a new 10-bit sample. Returns the new 8-bit value of the input if it has changed
+    /// enough since the last time a value was returned.
+    pub fn update(&mut self, sample: u16) -> Option<u8> {
+        debug_assert!(sample < 1024);
+
+        let average = match self.average {
+            // Each new sample counts for 1/8th of the average.
+            Some(average) => average - average / 8 + sample * 2,
+            None => sample * 16,
+        };
+        let is_first = self.average.is_none();
+        self.average = Some(average);
+
+        let value = u8::try_from(average >> 6).unwrap();
+        let difference = value.abs_diff(self.reported);
+
+        // The extreme values are always reported, so that they can be reached no matter the
+        // hysteresis.
+        if is_first || difference >= HYSTERESIS || (difference != 0 && (value == 0 || value == 255))
+        {
+            self.reported = value;
+            Some(value)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds the same sample until the input stops reporting new values, and returns the last
+    /// value reported.
+    fn settle(input: &mut AnalogInput, sample: u16) -> Option<u8> {
+        (0..100).filter_map(|_| input.update(sample)).last()
+    }
+
+    #[test]
+    fn hysteresis() {
+        let mut input = AnalogInput::new();
+        // The first sample is always reported.
+        assert_eq!(input.update(512), Some(128));
+        // Noise isn't.
+        for sample in [515, 509, 514, 510, 512] {
+            assert_eq!(input.update(sample), None);
+        }
+        assert_eq!(settle(&mut input, 516), None);
+
+        // Larger moves are, in steps of at least `HYSTERESIS`.
+        let mut reported = 128;
+        for _ in 0..100 {
+            if let Some(value) = input.update(600) {
+                assert!(value - reported >= HYSTERESIS);
+                reported = value;
+            }
+        }
+        assert!(reported.abs_diff(150) < HYSTERESIS);
+    }
+
+    #[test]
+    fn extremes() {
+        let mut input = AnalogInput::new();
+        input.update(512);
+        assert_eq!(settle(&mut input, 1023), Some(255));
+        assert_eq!(settle(&mut input, 0), Some(0));
+        assert_eq!(settle(&mut input, 1023), Some(255));
+    }
+
+    #[test]
+    fn parameters() {
+        let mut settings = Settings::new();
+        Parameter::Brightness.apply(&mut settings, Mode::Fire, 0);
+        assert_eq!(settings.brightness, settings::MIN_BRIGHTNESS);
+        Parameter::Brightness.apply(&mut settings, Mode::Fire, 200);
+        assert_eq!(settings.brightness, 200);
+
+        Parameter::Speed.apply(&mut settings, Mode::Fire, 100);
+        assert_eq!(settings.speed(Mode::Fire), 100);
+        assert_eq!(settings.speed(Mode::Twinkle), settings::NORMAL_SPEED);
+
+        Parameter::SegmentWidth.apply(&mut settings, Mode::Fire, 255);
+        assert_eq!(settings.segment_lights.segment_width, 17);
+    }
+}
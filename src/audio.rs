@@ -0,0 +1,240 @@
+// Analysis of the sound captured by a microphone module connected to an analog input.
+//
+// Each frame, a batch of samples is read from the ADC and analyzed on the fly, without being
+// stored anywhere, as we don't have the memory to spare. The signal is split into three bands
+// with simple one-pole low-pass filters rather than with a Fourier transform, which would
+// require a buffer of samples and too much computation time.
+//
+// Reading a sample takes around 104µs, giving a sample rate of around 9.6kHz. The bass band is
+// below ~200Hz, the treble band above ~1kHz, and the mid band in-between. A one-pole filter with
+// a higher cutoff would hardly attenuate anything below the Nyquist frequency of 4.8kHz.
+
+use core::cmp;
+
+/// Analog input the microphone module is connected to. A0 to A3 are used by the
+/// potentiometers (see the `analog` module).
+#[cfg(target_arch = "avr")]
+pub const MIC_CHANNEL: u8 = 4;
+
+/// Number of samples to analyze every frame.
+pub const SAMPLES_PER_FRAME: usize = 64;
+
+/// Minimum value of the peak used for normalizing, in order to not amplify the noise of the
+/// microphone to full scale when the room is silent.
+const MIN_PEAK: u16 = 200;
+
+/// Minimum bass level, before normalizing, for a beat to be detected.
+const BEAT_MIN_BASS: u16 = 150;
+
+/// Number of frames after a beat during which no other beat can be detected.
+const BEAT_COOLDOWN_FRAMES: u8 = 8;
+
+/// Result of analyzing one frame of sound. All the levels are between 0 and 255.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct AudioFrame {
+    /// Overall loudness.
+    pub level: u8,
+    pub bass: u8,
+    pub mid: u8,
+    pub treble: u8,
+    /// `true` if a beat has been detected during this frame.
+    pub beat: bool,
+    /// Set to 255 when a beat is detected, then decays over the following frames.
+    pub flash: u8,
+}
+
+/// State of the analysis, kept between frames.
+#[derive(Debug, Clone)]
+pub struct AudioAnalyzer {
+    /// Moving average of the samples (multiplied by 16), multiplied by 256. This is the DC
+    /// offset of the microphone module, normally around half of the ADC range.
+    dc: i32,
+    /// State of the ~200Hz low-pass filter.
+    low_pass_bass: i32,
+    /// State of the ~1kHz low-pass filter.
+    low_pass_mid: i32,
+    /// Slowly decaying maximum of the levels, used to normalize them.
+    peak: u16,
+    /// Moving average of the bass level, used for the beat detection.
+    bass_average: u16,
+    /// Bass level of the previous frame, used for the beat detection.
+    last_bass: u16,
+    beat_cooldown: u8,
+    flash: u8,
+}
+
+impl AudioAnalyzer {
+    pub const fn new() -> Self {
+        AudioAnalyzer {
+            dc: 512 * 16 * 256,
+            low_pass_bass: 0,
+            low_pass_mid: 0,
+            peak: MIN_PEAK,
+            bass_average: 0,
+            last_bass: 0,
+            beat_cooldown: 0,
+            flash: 0,
+        }
+    }
+
+    /// Analyzes a frame of 10-bit samples.
+    pub fn analyze(&mut self, samples: impl Iterator<Item = u16>) -> AudioFrame {
+        let mut num_samples = 0u32;
+        let mut level_sum = 0u32;
+        let mut bass_sum = 0u32;
+        let mut mid_sum = 0u32;
+        let mut treble_sum = 0u32;
+
+        for sample in samples {
+            debug_assert!(sample < 1024);
+            let sample = i32::from(sample) * 16;
+
+            self.dc += sample - (self.dc >> 8);
+            let x = sample - (self.dc >> 8);
+
+            // One-pole low-pass filters, with coefficients 1/8 (~200Hz) and 1/2 (~1kHz).
+            self.low_pass_bass += (x - self.low_pass_bass) >> 3;
+            self.low_pass_mid += (x - self.low_pass_mid) >> 1;
+
+            num_samples += 1;
+            level_sum += x.unsigned_abs();
+            bass_sum += self.low_pass_bass.unsigned_abs();
+            mid_sum += (self.low_pass_mid - self.low_pass_bass).unsigned_abs();
+            treble_sum += (x - self.low_pass_mid).unsigned_abs();
+        }
+
+        let mean = |sum: u32| u16::try_from(sum / cmp::max(num_samples, 1)).unwrap_or(u16::MAX);
+        let level = mean(level_sum);
+        let bass = mean(bass_sum);
+        let mid = mean(mid_sum);
+        let treble = mean(treble_sum);
+
+        self.peak = cmp::max(
+            cmp::max(self.peak - self.peak / 64, MIN_PEAK),
+            cmp::max(cmp::max(level, bass), cmp::max(mid, treble)),
+        );
+        let normalize = |value: u16| {
+            u8::try_from(cmp::min(u32::from(value) * 255 / u32::from(self.peak), 255)).unwrap()
+        };
+
+        // The bass must be rising, so that the tail of a loud beat following a silence isn't
+        // detected as another beat once the cooldown is over.
+        let beat = self.beat_cooldown == 0
+            && bass >= BEAT_MIN_BASS
+            && bass > self.last_bass
+            && u32::from(bass) * 2 > u32::from(self.bass_average) * 3;
+        self.bass_average = self.bass_average - self.bass_average / 16 + bass / 16;
+        self.last_bass = bass;
+        if beat {
+            self.beat_cooldown = BEAT_COOLDOWN_FRAMES;
+            self.flash = 255;
+        } else {
+            self.beat_cooldown = self.beat_cooldown.saturating_sub(1);
+            self.flash = self.flash.saturating_sub(24);
+        }
+
+        AudioFrame {
+            level: normalize(level),
+            bass: normalize(bass),
+            mid: normalize(mid),
+            treble: normalize(treble),
+            beat,
+            flash: self.flash,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the samples of an 8-bit mono WAV file recorded at the sample rate of the
+    /// microphone, as values of the ADC. See `testdata/audio/generate.py`.
+    fn read_wav(wav: &[u8]) -> Vec<u16> {
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..12], b"WAVE");
+        let mut chunks = &wav[12..];
+        let mut format = None;
+        loop {
+            let len = u32::from_le_bytes(chunks[4..8].try_into().unwrap()) as usize;
+            let (id, data) = (&chunks[0..4], &chunks[8..8 + len]);
+            match id {
+                b"fmt " => format = Some(data),
+                b"data" => {
+                    let format = format.unwrap();
+                    // PCM, mono, 9600Hz, 8 bits per sample.
+                    assert_eq!(u16::from_le_bytes([format[0], format[1]]), 1);
+                    assert_eq!(u16::from_le_bytes([format[2], format[3]]), 1);
+                    assert_eq!(u32::from_le_bytes(format[4..8].try_into().unwrap()), 9600);
+                    assert_eq!(u16::from_le_bytes([format[14], format[15]]), 8);
+                    // The 8-bit samples are centered on 128, and the ADC on 512.
+                    return data.iter().map(|sample| u16::from(*sample) * 4).collect();
+                }
+                _ => {}
+            }
+            chunks = &chunks[8 + len + len % 2..];
+        }
+    }
+
+    /// Analyzes the given WAV file frame by frame.
+    fn analyze_wav(wav: &[u8]) -> Vec<AudioFrame> {
+        let mut analyzer = AudioAnalyzer::new();
+        read_wav(wav)
+            .chunks(SAMPLES_PER_FRAME)
+            .map(|samples| analyzer.analyze(samples.iter().copied()))
+            .collect()
+    }
+
+    /// Returns the band with the highest level: 0 for bass, 1 for mid, 2 for treble.
+    fn loudest_band(frame: &AudioFrame) -> usize {
+        let bands = [frame.bass, frame.mid, frame.treble];
+        (0..3).max_by_key(|band| bands[*band]).unwrap()
+    }
+
+    /// Number of frames analyzed during 0.1s of sound.
+    const FRAMES_PER_100_MS: usize = 960 / SAMPLES_PER_FRAME;
+
+    #[test]
+    fn silence() {
+        for frame in analyze_wav(include_bytes!("../testdata/audio/silence.wav")) {
+            // The noise of the microphone isn't amplified to full scale.
+            assert!(frame.level < 64, "{frame:?}");
+            assert!(!frame.beat);
+        }
+    }
+
+    #[test]
+    fn kicks() {
+        // A kick every 0.5s, which is every 75 frames.
+        let frames = analyze_wav(include_bytes!("../testdata/audio/kicks.wav"));
+        let beats: Vec<_> = (0..frames.len()).filter(|n| frames[*n].beat).collect();
+        assert_eq!(beats, [0, 75, 150, 225]);
+        for n in beats {
+            assert_eq!(loudest_band(&frames[n]), 0, "{:?}", frames[n]);
+            assert_eq!(frames[n].flash, 255);
+            // The flash fades out before the next kick.
+            assert_eq!(frames[n + 74].flash, 0);
+        }
+    }
+
+    #[test]
+    fn mid_tone() {
+        let frames = analyze_wav(include_bytes!("../testdata/audio/tone_500hz.wav"));
+        for frame in &frames[FRAMES_PER_100_MS..] {
+            assert_eq!(loudest_band(frame), 1, "{frame:?}");
+            assert!(frame.level > 200, "{frame:?}");
+            // A steady sound only makes beats when it starts.
+            assert!(!frame.beat);
+        }
+    }
+
+    #[test]
+    fn treble_tone() {
+        let frames = analyze_wav(include_bytes!("../testdata/audio/tone_3khz.wav"));
+        for frame in &frames[FRAMES_PER_100_MS..] {
+            assert_eq!(loudest_band(frame), 2, "{frame:?}");
+            assert!(frame.level > 200, "{frame:?}");
+        }
+        assert!(frames.iter().all(|frame| !frame.beat));
+    }
+}
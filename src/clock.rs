@@ -0,0 +1,188 @@
+// Monotonic clock of the device.
+//
+// Time is measured by counting the overflows of timer0, which happen every 1024µs. The interrupt
+// handler counts them in a `u32`, which wraps around after about 51 days, and the lights are
+// expected to stay on for longer than that. `Clock` extends this counter to 64 bits, so that the
+// clock values it returns never wrap around in practice and can be compared and subtracted
+// safely.
+//
+// Effects animate from the clock value, usually by dividing the number of milliseconds. Casting
+// it to a smaller integer first makes the animation jump every time the integer wraps around.
+// Effects should instead take the clock value modulo the period of their animation, with
+// `millis_in_period`.
+
+use core::time::Duration;
+
+/// Duration between two overflows of timer0: it counts up to 256 with a prescaler of 64, with a
+/// 16MHz clock.
+const OVERFLOW_MICROS: u64 = 1024;
+
+/// Duration of one tick of timer0.
+const TICK_MICROS: u64 = 4;
+
+/// Number of CPU cycles in one tick of timer0.
+const TICK_CYCLES: u32 = 64;
+
+#[derive(Debug, Clone)]
+pub struct Clock {
+    /// Total number of overflows of timer0 up to the last reading.
+    num_overflows: u64,
+    /// Value of the counter of overflows of the interrupt handler at the last reading.
+    last_raw_num_overflows: u32,
+}
+
+impl Clock {
+    pub const fn new() -> Self {
+        Clock {
+            num_overflows: 0,
+            last_raw_num_overflows: 0,
+        }
+    }
+
+    /// Returns the time elapsed since timer0 has been enabled, given the counter of overflows of
+    /// the interrupt handler, the value of timer0, and whether timer0 has overflowed since
+    /// without the interrupt handler having run yet. All three must be read while interrupts are
+    /// disabled, and timer0 must be read before checking for a pending overflow.
+    ///
+    /// The counter of overflows wraps around, so this must be called at least once every
+    /// 51 days.
+    pub fn now(&mut self, raw_num_overflows: u32, timer0: u8, overflow_pending: bool) -> Duration {
+        self.num_overflows +=
+            u64::from(raw_num_overflows.wrapping_sub(self.last_raw_num_overflows));
+        self.last_raw_num_overflows = raw_num_overflows;
+
+        // If timer0 overflowed while interrupts are disabled, its value has restarted from 0 but
+        // the overflow hasn't been counted yet, and the clock would go back in time. If timer0 was
+        // at its maximum value, the overflow happened after reading it.
+        let num_overflows = if overflow_pending && timer0 < u8::MAX {
+            self.num_overflows + 1
+        } else {
+            self.num_overflows
+        };
+
+        Duration::from_micros(num_overflows * OVERFLOW_MICROS + u64::from(timer0) * TICK_MICROS)
+    }
+}
+
+/// Returns the number of overflows of timer0 that its interrupt handler misses while interrupts
+/// are disabled for about `cycles` CPU cycles, given the value of timer0 before and after, and
+/// whether an overflow was already pending before. Only one overflow can be pending, so the
+/// interrupt handler only counts one of them once interrupts are enabled again.
+///
+/// The number of cycles only needs to be right within 128 ticks of timer0: the value of timer0
+/// after gives the exact number of ticks elapsed, modulo 256.
+pub fn missed_overflows(
+    timer0_before: u8,
+    overflow_pending: bool,
+    timer0_after: u8,
+    cycles: u32,
+) -> u32 {
+    let estimated_ticks = u32::from(timer0_before) + cycles / TICK_CYCLES;
+    let error = timer0_after.wrapping_sub(estimated_ticks as u8) as i8;
+    let ticks = estimated_ticks.wrapping_add(i32::from(error) as u32);
+    // Same as in `Clock::now`, if timer0 was at its maximum value, the pending overflow happened
+    // after reading it, and is already counted in the ticks.
+    let num_overflows = ticks / 256 + u32::from(overflow_pending && timer0_before < u8::MAX);
+    num_overflows.saturating_sub(1)
+}
+
+/// Returns the number of milliseconds elapsed since the beginning of the current period of
+/// `period_ms` milliseconds, given a clock value. Unlike casting the number of milliseconds to a
+/// smaller integer, this stays continuous when the clock value grows large.
+pub fn millis_in_period(clock_value: Duration, period_ms: u32) -> u32 {
+    let period_ms = u64::from(period_ms);
+    let secs_in_period = clock_value.as_secs() % period_ms;
+    ((secs_in_period * 1000 + u64::from(clock_value.subsec_millis())) % period_ms) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_uptime() {
+        // The interrupt handler counts the overflows in a `u32`, which wraps around several times
+        // during a year.
+        let mut clock = Clock::new();
+        let mut previous = Duration::ZERO;
+        let mut total_overflows = 0u64;
+        for _ in 0..400 {
+            total_overflows += 1 << 28;
+            let now = clock.now(total_overflows as u32, 100, false);
+            assert_eq!(now, Duration::from_micros(total_overflows * 1024 + 400));
+            assert!(now > previous);
+            previous = now;
+        }
+        assert!(previous > Duration::from_secs(3 * 365 * 24 * 3600));
+    }
+
+    #[test]
+    fn pending_overflow() {
+        let mut clock = Clock::new();
+        let raw = u32::MAX;
+        let before = clock.now(raw, 250, false);
+        // Timer0 has overflowed and restarted from 0, but the interrupt handler hasn't run yet.
+        let pending = clock.now(raw, 2, true);
+        assert_eq!(pending, before + Duration::from_micros(8 * 4));
+        // Then the interrupt handler has run, wrapping the counter around.
+        let after = clock.now(raw.wrapping_add(1), 3, false);
+        assert_eq!(after, pending + Duration::from_micros(4));
+        // Timer0 overflowed after being read at its maximum value.
+        let at_max = clock.now(raw.wrapping_add(1), 255, true);
+        assert_eq!(at_max, after + Duration::from_micros(252 * 4));
+    }
+
+    #[test]
+    fn missed_overflows_during_upload() {
+        // Sending the data of the north-west strip takes 252 bytes of 188 cycles, or 740.25
+        // ticks of timer0.
+        let cycles = 252 * 188;
+        for timer0_before in 0..=u8::MAX {
+            for overflow_pending in [false, true] {
+                // The number of cycles doesn't account for the code around the upload.
+                for extra_ticks in 0..20 {
+                    let ticks = u32::from(timer0_before) + 740 + extra_ticks;
+                    let num_overflows =
+                        ticks / 256 + u32::from(overflow_pending && timer0_before < u8::MAX);
+                    let missed =
+                        missed_overflows(timer0_before, overflow_pending, ticks as u8, cycles);
+                    // The interrupt handler counts one overflow once interrupts are enabled.
+                    assert_eq!(missed + 1, num_overflows, "{timer0_before} {extra_ticks}");
+                }
+            }
+        }
+
+        // Short enough for timer0 not to overflow.
+        assert_eq!(missed_overflows(10, false, 20, 640), 0);
+        assert_eq!(missed_overflows(250, false, 4, 640), 0);
+        assert_eq!(missed_overflows(250, true, 4, 640), 1);
+    }
+
+    #[test]
+    fn millis_in_period_long_uptime() {
+        for millis in [
+            0,
+            999,
+            1_000,
+            123_456_789,
+            u64::from(u32::MAX),
+            1 << 32,
+            (1 << 32) + 1,
+            // Ten years.
+            315_360_000_000,
+        ] {
+            for period_ms in [1, 300, 5_000, 60_000, 500 * 173, 256 * 65_535, u32::MAX] {
+                assert_eq!(
+                    u64::from(millis_in_period(Duration::from_millis(millis), period_ms)),
+                    millis % u64::from(period_ms),
+                    "{millis} {period_ms}"
+                );
+            }
+        }
+
+        // Continuous where the milliseconds wrap around a `u32`.
+        let before = millis_in_period(Duration::from_millis(u64::from(u32::MAX)), 60_000);
+        let after = millis_in_period(Duration::from_millis(1 << 32), 60_000);
+        assert_eq!(after, (before + 1) % 60_000);
+    }
+}
@@ -0,0 +1,291 @@
+// Color types and the fixed-point math used to manipulate them.
+//
+// The conversions from HSV are the same as the ones of the FastLED library: the "rainbow" one
+// gives more room to yellow and makes the colors look evenly spaced to the eye, while the
+// "spectrum" one is a mathematically straight conversion.
+
+/// Color of an LED, in the order red, green, blue.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Rgb(pub [u8; 3]);
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb([0, 0, 0]);
+    pub const WHITE: Rgb = Rgb([255, 255, 255]);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb([r, g, b])
+    }
+
+    pub const fn r(self) -> u8 {
+        self.0[0]
+    }
+
+    pub const fn g(self) -> u8 {
+        self.0[1]
+    }
+
+    pub const fn b(self) -> u8 {
+        self.0[2]
+    }
+
+    /// Applies `f` to each channel.
+    pub fn map(self, f: impl FnMut(u8) -> u8) -> Rgb {
+        Rgb(self.0.map(f))
+    }
+
+    /// Applies `f` to each channel of `self` and the same channel of `other`.
+    pub fn zip_map(self, other: Rgb, mut f: impl FnMut(u8, u8) -> u8) -> Rgb {
+        Rgb([
+            f(self.0[0], other.0[0]),
+            f(self.0[1], other.0[1]),
+            f(self.0[2], other.0[2]),
+        ])
+    }
+
+    /// Adds the channels of the two colors, clamping them at 255.
+    pub fn saturating_add(self, other: Rgb) -> Rgb {
+        self.zip_map(other, u8::saturating_add)
+    }
+
+    /// Multiplies all the channels by `scale`, where 255 is 1. See [`scale8`].
+    pub fn scale(self, scale: u8) -> Rgb {
+        self.map(|c| scale8(c, scale))
+    }
+
+    /// Interpolates between `self` and `other`. See [`lerp`].
+    pub fn lerp(self, other: Rgb, amount: u8) -> Rgb {
+        self.zip_map(other, |a, b| lerp(a, b, amount))
+    }
+
+    /// Moves a fully saturated color of maximum value towards white depending on `sat`, then
+    /// dims it depending on `val`.
+    fn saturate_and_dim(self, sat: u8, val: u8) -> Rgb {
+        let color = match sat {
+            255 => self,
+            0 => Rgb::WHITE,
+            _ => {
+                let desat = scale8(255 - sat, 255 - sat);
+                self.map(|c| scale8(c, 255 - desat) + desat)
+            }
+        };
+
+        match val {
+            255 => color,
+            0 => Rgb::BLACK,
+            _ => color.scale(scale8_video(val, val)),
+        }
+    }
+}
+
+impl From<Hsv> for Rgb {
+    fn from(hsv: Hsv) -> Rgb {
+        hsv.to_rgb_rainbow()
+    }
+}
+
+/// Color expressed as hue, saturation and value, all between 0 and 255. A hue of 256 would be a
+/// full turn.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Hsv {
+    pub hue: u8,
+    pub sat: u8,
+    pub val: u8,
+}
+
+impl Hsv {
+    pub const fn new(hue: u8, sat: u8, val: u8) -> Self {
+        Hsv { hue, sat, val }
+    }
+
+    /// Converts to RGB, giving each of the eight main hues (red, orange, yellow, green, aqua,
+    /// blue, purple, pink) an eighth of the hue range.
+    pub fn to_rgb_rainbow(self) -> Rgb {
+        let offset8 = (self.hue & 0x1f) << 3;
+        let third = scale8(offset8, 85);
+        let two_thirds = scale8(offset8, 170);
+
+        let [r, g, b] = match self.hue >> 5 {
+            // Red to orange.
+            0 => [255 - third, third, 0],
+            // Orange to yellow.
+            1 => [171, 85 + third, 0],
+            // Yellow to green.
+            2 => [171 - two_thirds, 170 + third, 0],
+            // Green to aqua.
+            3 => [0, 255 - third, third],
+            // Aqua to blue.
+            4 => [0, 171 - two_thirds, 85 + two_thirds],
+            // Blue to purple.
+            5 => [third, 0, 255 - third],
+            // Purple to pink.
+            6 => [85 + third, 0, 171 - third],
+            // Pink to red.
+            7 => [170 + third, 0, 85 - third],
+            _ => unreachable!(),
+        };
+
+        Rgb::new(r, g, b).saturate_and_dim(self.sat, self.val)
+    }
+
+    /// Converts to RGB, giving each of red, green and blue a third of the hue range.
+    pub fn to_rgb_spectrum(self) -> Rgb {
+        let hue = scale8(self.hue, 191);
+        let offset = u16::from(hue & 0x3f);
+
+        let floor = (u16::from(self.val) * u16::from(255 - self.sat) / 256) as u8;
+        let amplitude = u16::from(self.val - floor);
+        let ramp_up = (offset * amplitude / 64) as u8 + floor;
+        let ramp_down = ((0x3f - offset) * amplitude / 64) as u8 + floor;
+
+        match hue >> 6 {
+            0 => Rgb::new(ramp_down, ramp_up, floor),
+            1 => Rgb::new(floor, ramp_down, ramp_up),
+            2 => Rgb::new(ramp_up, floor, ramp_down),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Multiplies `value` by `scale`, where 255 is 1.
+pub fn scale8(value: u8, scale: u8) -> u8 {
+    ((u16::from(value) * (u16::from(scale) + 1)) >> 8) as u8
+}
+
+/// Same as [`scale8`], except that the result is never 0 unless one of the inputs is 0. Used to
+/// dim colors without making them disappear.
+pub fn scale8_video(value: u8, scale: u8) -> u8 {
+    let scaled = ((u16::from(value) * u16::from(scale)) >> 8) as u8;
+    if value != 0 && scale != 0 {
+        scaled + 1
+    } else {
+        scaled
+    }
+}
+
+/// Interpolates between `a` and `b`, where an `amount` of 0 gives `a` and 255 gives `b`.
+pub fn lerp(a: u8, b: u8, amount: u8) -> u8 {
+    ((u16::from(a) * u16::from(255 - amount) + u16::from(b) * u16::from(amount)) / 255) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hues of the rainbow conversion at the start of each eighth of the hue range.
+    const RAINBOW_ANCHORS: [[f64; 3]; 8] = [
+        [255.0, 0.0, 0.0],
+        [171.0, 85.0, 0.0],
+        [171.0, 170.0, 0.0],
+        [0.0, 255.0, 0.0],
+        [0.0, 171.0, 85.0],
+        [0.0, 0.0, 255.0],
+        [85.0, 0.0, 171.0],
+        [170.0, 0.0, 85.0],
+    ];
+
+    /// Floating-point version of the rainbow conversion: linear interpolation between the main
+    /// hues, then the same quadratic curves as the fixed-point version for the saturation and
+    /// the value.
+    fn rainbow_reference(hsv: Hsv) -> [f64; 3] {
+        let section = usize::from(hsv.hue >> 5);
+        let progress = f64::from(hsv.hue & 0x1f) / 32.0;
+        let from = RAINBOW_ANCHORS[section];
+        let to = RAINBOW_ANCHORS[(section + 1) % 8];
+        let desat = (1.0 - f64::from(hsv.sat) / 255.0).powi(2);
+        let dim = (f64::from(hsv.val) / 255.0).powi(2);
+        [0, 1, 2].map(|n| {
+            let hue = from[n] + (to[n] - from[n]) * progress;
+            (hue * (1.0 - desat) + 255.0 * desat) * dim
+        })
+    }
+
+    /// Floating-point version of the spectrum conversion: straight ramps between red, green and
+    /// blue, going to white with the saturation and to black with the value.
+    fn spectrum_reference(hsv: Hsv) -> [f64; 3] {
+        let hue = f64::from(hsv.hue) / 256.0 * 3.0;
+        let progress = hue.fract();
+        let ramps = match hue as u8 {
+            0 => [1.0 - progress, progress, 0.0],
+            1 => [0.0, 1.0 - progress, progress],
+            _ => [progress, 0.0, 1.0 - progress],
+        };
+        let val = f64::from(hsv.val);
+        let sat = f64::from(hsv.sat) / 255.0;
+        ramps.map(|ramp| val * (1.0 - sat) + val * sat * ramp)
+    }
+
+    /// Returns the largest difference between the channels of a conversion and its reference,
+    /// for all the colors.
+    fn max_error(convert: impl Fn(Hsv) -> Rgb, reference: impl Fn(Hsv) -> [f64; 3]) -> f64 {
+        let mut max_error = 0.0f64;
+        for hue in 0..=255 {
+            for sat in (0..=255).step_by(5) {
+                for val in (0..=255).step_by(5) {
+                    let hsv = Hsv::new(hue, sat, val);
+                    for (n, expected) in convert(hsv).0.iter().zip(reference(hsv)) {
+                        max_error = max_error.max((f64::from(*n) - expected).abs());
+                    }
+                }
+            }
+        }
+        max_error
+    }
+
+    #[test]
+    fn rainbow() {
+        assert!(max_error(Hsv::to_rgb_rainbow, rainbow_reference) < 4.0);
+        for (hue, anchor) in (0..=255).step_by(32).zip(RAINBOW_ANCHORS) {
+            assert_eq!(
+                Hsv::new(hue, 255, 255).to_rgb_rainbow().0.map(f64::from),
+                anchor
+            );
+        }
+        assert_eq!(Hsv::new(123, 0, 255).to_rgb_rainbow(), Rgb::WHITE);
+        assert_eq!(Hsv::new(123, 255, 0).to_rgb_rainbow(), Rgb::BLACK);
+    }
+
+    #[test]
+    fn spectrum() {
+        // Like in FastLED, the ramps only go up to 63/64 of the value, so the primary colors are
+        // slightly dimmer than with the reference.
+        assert!(max_error(Hsv::to_rgb_spectrum, spectrum_reference) < 6.0);
+        // And white is 254.
+        assert_eq!(
+            Hsv::new(123, 0, 255).to_rgb_spectrum(),
+            Rgb::new(254, 254, 254)
+        );
+        assert_eq!(Hsv::new(123, 255, 0).to_rgb_spectrum(), Rgb::BLACK);
+    }
+
+    #[test]
+    fn fixed_point() {
+        for a in 0..=255 {
+            for b in 0..=255 {
+                let product = f64::from(a) * f64::from(b) / 255.0;
+                assert!((f64::from(scale8(a, b)) - product).abs() <= 1.0, "{a} {b}");
+                assert!(
+                    (f64::from(scale8_video(a, b)) - product).abs() <= 1.0,
+                    "{a} {b}"
+                );
+                assert_eq!(scale8_video(a, b) == 0, a == 0 || b == 0, "{a} {b}");
+            }
+            assert_eq!(scale8(a, 255), a);
+        }
+
+        for (a, b) in [(0, 255), (255, 0), (10, 200), (200, 10), (77, 77)] {
+            for amount in 0..=255 {
+                let expected =
+                    f64::from(a) + (f64::from(b) - f64::from(a)) * f64::from(amount) / 255.0;
+                assert!((f64::from(lerp(a, b, amount)) - expected).abs() < 1.0);
+            }
+            assert_eq!(lerp(a, b, 0), a);
+            assert_eq!(lerp(a, b, 255), b);
+        }
+
+        assert_eq!(
+            Rgb::new(200, 5, 0).saturating_add(Rgb::new(100, 5, 0)),
+            Rgb::new(255, 10, 0)
+        );
+    }
+}
@@ -0,0 +1,106 @@
+// Simulation of fire, based on the "Fire2012" example of the FastLED library.
+//
+// Each wall has two flames, one rising from each of its corners towards its middle. A flame is
+// an array of heat values, one per LED, starting from the corner. At each step of the
+// simulation, every cell cools down a little, the heat drifts away from the corner while
+// diffusing, and new sparks randomly ignite near the corner. The heat is then converted to
+// colors through a palette.
+
+use core::cmp;
+
+use crate::{
+    color,
+    leds::{EAST_LEDS, NORTH_LEDS, SOUTH_LEDS, TOTAL_LEDS, WEST_LEDS},
+    pattern::{Frame, FrameContext, ModeState, Pattern, Stepper},
+    random::Rng,
+};
+
+/// Number of cells, starting from the corner, where new sparks can appear.
+const SPARK_ZONE: u8 = 7;
+
+/// State of [`crate::leds::Mode::Fire`].
+#[derive(Debug, Clone)]
+pub struct FireState {
+    /// Heat of each LED, in the order of [`flames`] rather than in layout order.
+    heat: [u8; TOTAL_LEDS],
+    stepper: Stepper,
+}
+
+impl FireState {
+    pub const fn new() -> Self {
+        FireState {
+            heat: [0; TOTAL_LEDS],
+            stepper: Stepper::new(),
+        }
+    }
+}
+
+pub struct Fire;
+
+impl Pattern for Fire {
+    fn render(&self, ctx: &FrameContext, state: &mut ModeState, frame: &mut Frame) {
+        let params = &ctx.settings.fire;
+        let state = state.fire();
+
+        let num_steps = state.stepper.advance(ctx.clock_value);
+        let mut heat = &mut state.heat[..];
+        for (start, len, reversed) in flames() {
+            let (flame, rest) = heat.split_at_mut(len);
+            heat = rest;
+
+            for _ in 0..num_steps {
+                step(flame, params.cooling, params.sparking, ctx.rng);
+            }
+
+            for (n, heat) in flame.iter().enumerate() {
+                let led_pos = if reversed {
+                    start + len - 1 - n
+                } else {
+                    start + n
+                };
+                // The last entry of the palette wraps around to the first one, so we don't go
+                // all the way up.
+                frame[led_pos] = params.palette.get(color::scale8(*heat, 240));
+            }
+        }
+    }
+}
+
+/// Returns the position of the first LED and the number of LEDs of each flame, and whether the
+/// flame goes in the direction of decreasing positions.
+fn flames() -> impl Iterator<Item = (usize, usize, bool)> {
+    [
+        (0, WEST_LEDS),
+        (WEST_LEDS, NORTH_LEDS),
+        (WEST_LEDS + NORTH_LEDS, EAST_LEDS),
+        (WEST_LEDS + NORTH_LEDS + EAST_LEDS, SOUTH_LEDS),
+    ]
+    .into_iter()
+    .flat_map(|(start, len)| {
+        [
+            (start, len / 2, false),
+            (start + len / 2, len - len / 2, true),
+        ]
+    })
+}
+
+/// Performs a step of the simulation of the given flame, whose first cell is at the corner.
+fn step(flame: &mut [u8], cooling: u8, sparking: u8, rng: &Rng) {
+    debug_assert!(flame.len() > usize::from(SPARK_ZONE));
+
+    // The shorter the flame, the faster it has to cool down in order for the flames to not reach
+    // the end.
+    let max_cooling = cmp::min(u16::from(cooling) * 10 / flame.len() as u16 + 2, 255) as u8;
+    for heat in flame.iter_mut() {
+        *heat = heat.saturating_sub(rng.random8_below(max_cooling));
+    }
+
+    for n in (2..flame.len()).rev() {
+        flame[n] = ((u16::from(flame[n - 1]) + 2 * u16::from(flame[n - 2])) / 3) as u8;
+    }
+
+    if rng.random8() < sparking {
+        let n = usize::from(rng.random8_below(SPARK_ZONE));
+        flame[n] = flame[n].saturating_add(rng.random8_between(160, 255));
+    }
+}
@@ -0,0 +1,199 @@
+// Frame rate limiter.
+//
+// Rendering a frame takes more or less time depending on the mode. Instead of waiting for a fixed
+// duration after each frame, the main loop waits until the frame has lasted the target frame
+// duration, so that all modes update at the same steady rate. If a frame took longer to render
+// than the target duration, the next one starts right away, without trying to catch up.
+//
+// The limiter also measures the actual frame rate and the time spent rendering and sending the
+// frames, which are reported by the `status` command of the serial port.
+
+use core::{cmp, time::Duration};
+
+/// Frame rate targeted at startup.
+pub const DEFAULT_FPS: u8 = 50;
+
+/// Range of frame rates that can be targeted.
+pub const MIN_FPS: u8 = 1;
+pub const MAX_FPS: u8 = 100;
+
+/// The LEDs only take into account the data they received once their data line has stayed low
+/// for 280µs, so there must be at least this much time between two frames, even if rendering is
+/// slow.
+const MIN_GAP: Duration = Duration::from_micros(300);
+
+/// Duration over which the frame rate is measured.
+const MEASUREMENT_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct FrameLimiter {
+    target_fps: u8,
+    /// Value of the clock at the start of the current frame.
+    frame_start: Duration,
+    /// Value of the clock at the start of the current measurement window.
+    window_start: Duration,
+    /// Number of frames started during the current measurement window.
+    num_frames_in_window: u8,
+    /// Number of frames started during the last complete measurement window.
+    measured_fps: u8,
+    /// Moving average of the time between the start of a frame and the moment it has been sent,
+    /// in microseconds.
+    render_micros: u32,
+}
+
+impl FrameLimiter {
+    pub const fn new() -> Self {
+        FrameLimiter {
+            target_fps: DEFAULT_FPS,
+            frame_start: Duration::ZERO,
+            window_start: Duration::ZERO,
+            num_frames_in_window: 0,
+            measured_fps: 0,
+            render_micros: 0,
+        }
+    }
+
+    pub fn target_fps(&self) -> u8 {
+        self.target_fps
+    }
+
+    /// Sets the frame rate to target, clamped to the supported range.
+    pub fn set_target_fps(&mut self, fps: u8) {
+        self.target_fps = fps.clamp(MIN_FPS, MAX_FPS);
+    }
+
+    /// Number of frames during the last second.
+    pub fn measured_fps(&self) -> u8 {
+        self.measured_fps
+    }
+
+    /// Average time spent rendering and sending a frame, in microseconds.
+    pub fn render_micros(&self) -> u32 {
+        self.render_micros
+    }
+
+    /// Must be called at the start of each frame, with the current value of the clock.
+    pub fn start_frame(&mut self, clock_value: Duration) {
+        self.frame_start = clock_value;
+
+        if clock_value.saturating_sub(self.window_start) >= MEASUREMENT_WINDOW {
+            self.measured_fps = self.num_frames_in_window;
+            self.num_frames_in_window = 0;
+            self.window_start = clock_value;
+        }
+        self.num_frames_in_window = self.num_frames_in_window.saturating_add(1);
+    }
+
+    /// Must be called once the frame has been sent, with the current value of the clock. Returns
+    /// the value of the clock at which the next frame should start.
+    pub fn end_frame(&mut self, clock_value: Duration) -> Duration {
+        let render_time = clock_value.saturating_sub(self.frame_start);
+        let render_micros = u32::try_from(render_time.as_micros()).unwrap_or(u32::MAX);
+        self.render_micros = self.render_micros - self.render_micros / 8 + render_micros / 8;
+
+        let frame_duration = Duration::from_micros(1_000_000 / u64::from(self.target_fps));
+        cmp::max(self.frame_start + frame_duration, clock_value + MIN_GAP)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the frames of the main loop for the given duration, each frame taking `render_time`
+    /// to render and send, with a simulated clock. Returns the clock values at which each frame
+    /// started.
+    fn run(
+        limiter: &mut FrameLimiter,
+        clock_value: &mut Duration,
+        render_time: Duration,
+        duration: Duration,
+    ) -> Vec<Duration> {
+        let end = *clock_value + duration;
+        let mut starts = Vec::new();
+        while *clock_value < end {
+            starts.push(*clock_value);
+            limiter.start_frame(*clock_value);
+            *clock_value = limiter.end_frame(*clock_value + render_time);
+        }
+        starts
+    }
+
+    #[test]
+    fn steady_frame_rate() {
+        let mut limiter = FrameLimiter::new();
+        let mut clock_value = Duration::from_millis(123);
+        let starts = run(
+            &mut limiter,
+            &mut clock_value,
+            Duration::from_millis(5),
+            Duration::from_secs(3),
+        );
+        for pair in starts.windows(2) {
+            assert_eq!(pair[1] - pair[0], Duration::from_millis(20));
+        }
+        assert_eq!(limiter.measured_fps(), DEFAULT_FPS);
+        assert!(limiter.render_micros().abs_diff(5_000) < 50);
+
+        limiter.set_target_fps(100);
+        run(
+            &mut limiter,
+            &mut clock_value,
+            Duration::from_millis(5),
+            Duration::from_secs(3),
+        );
+        assert_eq!(limiter.measured_fps(), 100);
+    }
+
+    #[test]
+    fn slow_frames() {
+        // The next frame starts right away, apart from the minimum gap.
+        let mut limiter = FrameLimiter::new();
+        let mut clock_value = Duration::from_millis(123);
+        let starts = run(
+            &mut limiter,
+            &mut clock_value,
+            Duration::from_micros(19_900),
+            Duration::from_secs(3),
+        );
+        for pair in starts.windows(2) {
+            assert_eq!(pair[1] - pair[0], Duration::from_micros(19_900) + MIN_GAP);
+        }
+        assert_eq!(limiter.measured_fps(), 50);
+
+        run(
+            &mut limiter,
+            &mut clock_value,
+            Duration::from_millis(30),
+            Duration::from_secs(3),
+        );
+        // The window lasts until the first frame starting at least 1s after it started, so it
+        // holds the 34 frames starting in the first 1s / 30.3ms, rounded up.
+        assert_eq!(limiter.measured_fps(), 34);
+        assert!(limiter.render_micros().abs_diff(30_000) < 300);
+    }
+
+    #[test]
+    fn measurement_window() {
+        let mut limiter = FrameLimiter::new();
+        let mut clock_value = Duration::from_secs(5);
+        // Frames during the first window, of which the first one starts it.
+        for _ in 0..10 {
+            limiter.start_frame(clock_value);
+            clock_value += Duration::from_millis(50);
+        }
+        assert_eq!(limiter.measured_fps(), 0);
+        // Then nothing for a while, and the next frame ends the window.
+        limiter.start_frame(Duration::from_secs(60));
+        assert_eq!(limiter.measured_fps(), 10);
+    }
+
+    #[test]
+    fn target_range() {
+        let mut limiter = FrameLimiter::new();
+        limiter.set_target_fps(0);
+        assert_eq!(limiter.target_fps(), MIN_FPS);
+        limiter.set_target_fps(255);
+        assert_eq!(limiter.target_fps(), MAX_FPS);
+    }
+}
@@ -1,6 +1,3 @@
-#[cfg(not(target_arch = "avr"))]
-compile_error!("Can only work on AVR");
-
 // See <https://ww1.microchip.com/downloads/en/DeviceDoc/Atmel-7810-Automotive-Microcontrollers-ATmega328P_Datasheet.pdf>
 
 pub fn enable_bport_out<const PIN: usize>() {
@@ -36,8 +33,396 @@ pub fn read_bport<const PIN: usize>() -> bool {
     }
 }
 
-/// Sends the given data to the given PIN of port B.
-pub fn upload_bport_data<const PIN: usize>(input_data: &[u8]) {
+pub fn enable_dport_in<const PIN: usize>() {
+    unsafe {
+        core::arch::asm!(
+            "cbi {addr}, {pin}",
+            addr = const 0xa, pin = const PIN,
+            options(preserves_flags, nostack)
+        );
+    }
+}
+
+pub fn read_dport<const PIN: usize>() -> bool {
+    unsafe {
+        let out: u8;
+        core::arch::asm!(
+            "lds {out}, {addr}",
+            addr = const 0x29,
+            out = out(reg_upper) out,
+            options(preserves_flags, nostack)
+        );
+        (out & (1 << PIN)) != 0
+    }
+}
+
+/// Enables the `INT0` interrupt, which is triggered on any logical change of port D2.
+pub fn enable_int0_any_change() {
+    unsafe {
+        core::arch::asm!(
+            r#"
+            lds {tmp}, {eicra}
+            andi {tmp}, 0xfc
+            ori {tmp}, 0x01     // ISC00 = 1, ISC01 = 0
+            sts {eicra}, {tmp}
+            sbi {eimsk}, 0      // INT0
+            "#,
+            eicra = const 0x69, eimsk = const 0x1d,
+            tmp = out(reg_upper) _,
+            options(nostack)
+        );
+    }
+}
+
+/// Puts the MCU in idle sleep mode until the next interrupt. The CPU is stopped but the timers,
+/// the UART and the external interrupts keep running, and any of their interrupts wakes it up.
+///
+/// Interrupts must be enabled, otherwise the MCU never wakes up.
+pub fn sleep_idle() {
+    unsafe {
+        core::arch::asm!(
+            r#"
+            sts {smcr}, {enable}
+            sleep
+            sts {smcr}, {disable}
+            "#,
+            smcr = const 0x53,
+            enable = in(reg) 0b0000_0001u8,     // SE, SM2..0 = 0 (idle)
+            disable = in(reg) 0u8,
+            options(preserves_flags, nostack)
+        );
+    }
+}
+
+/// Enables the ADC, using AVcc as the voltage reference and a prescaler of 128, which gives an
+/// ADC clock of 125kHz.
+pub fn enable_adc() {
+    unsafe {
+        core::arch::asm!(
+            r#"
+            sts {admux}, {admux_val}
+            sts {adcsra}, {adcsra_val}
+            "#,
+            admux = const 0x7c, adcsra = const 0x7a,
+            admux_val = in(reg) 0b0100_0000u8,      // REFS0
+            adcsra_val = in(reg) 0b1000_0111u8,     // ADEN, ADPS2..0
+            options(preserves_flags, nostack)
+        );
+    }
+}
+
+/// Performs a conversion of the given analog input and returns its 10-bit value.
+///
+/// This blocks for the duration of the conversion, which is around 104µs.
+pub fn read_adc(channel: u8) -> u16 {
+    unsafe {
+        let low: u8;
+        let high: u8;
+        core::arch::asm!(
+            r#"
+            sts {admux}, {mux}
+            lds {tmp}, {adcsra}
+            ori {tmp}, 0x40     // ADSC
+            sts {adcsra}, {tmp}
+
+        1:
+            lds {tmp}, {adcsra}
+            sbrc {tmp}, 6       // ADSC is cleared at the end of the conversion
+            rjmp 1b
+
+            // ADCL must be read before ADCH.
+            lds {low}, {adcl}
+            lds {high}, {adch}
+            "#,
+            admux = const 0x7c, adcsra = const 0x7a,
+            adcl = const 0x78, adch = const 0x79,
+            mux = in(reg) 0b0100_0000u8 | (channel & 0xf),   // REFS0, MUX3..0
+            tmp = out(reg_upper) _,
+            low = out(reg) low,
+            high = out(reg) high,
+            options(nostack)
+        );
+        u16::from_le_bytes([low, high])
+    }
+}
+
+/// Enables the UART, with 8 data bits, no parity and one stop bit, as well as the interrupt
+/// triggered when a byte has been received. `ubrr` is the value of the baud rate register.
+pub fn enable_uart(ubrr: u16) {
+    let [low, high] = ubrr.to_le_bytes();
+    unsafe {
+        core::arch::asm!(
+            r#"
+            sts {ubrr0h}, {high}
+            sts {ubrr0l}, {low}
+            sts {ucsr0c}, {ucsr0c_val}
+            sts {ucsr0b}, {ucsr0b_val}
+            "#,
+            ubrr0h = const 0xc5, ubrr0l = const 0xc4,
+            ucsr0b = const 0xc1, ucsr0c = const 0xc2,
+            high = in(reg) high,
+            low = in(reg) low,
+            ucsr0c_val = in(reg) 0b0000_0110u8,     // UCSZ01, UCSZ00
+            ucsr0b_val = in(reg) 0b1001_1000u8,     // RXCIE0, RXEN0, TXEN0
+            options(preserves_flags, nostack)
+        );
+    }
+}
+
+/// Returns the last byte received by the UART. Reading it frees the space in the receive buffer
+/// of the UART.
+pub fn read_uart() -> u8 {
+    unsafe {
+        let out: u8;
+        core::arch::asm!(
+            "lds {out}, {addr}",
+            addr = const 0xc6,
+            out = out(reg_upper) out,
+            options(preserves_flags, nostack)
+        );
+        out
+    }
+}
+
+/// Sends a byte through the UART, waiting for the previous byte to have been sent first.
+pub fn write_uart(byte: u8) {
+    unsafe {
+        core::arch::asm!(
+            r#"
+        1:
+            lds {tmp}, {ucsr0a}
+            sbrs {tmp}, 5       // UDRE0 is set when the buffer can accept a new byte
+            rjmp 1b
+            sts {udr0}, {byte}
+            "#,
+            ucsr0a = const 0xc0, udr0 = const 0xc6,
+            byte = in(reg) byte,
+            tmp = out(reg_upper) _,
+            options(nostack)
+        );
+    }
+}
+
+/// Returns `true` if the UART has been enabled with [`enable_uart`].
+pub fn is_uart_enabled() -> bool {
+    unsafe {
+        let out: u8;
+        core::arch::asm!(
+            "lds {out}, {addr}",
+            addr = const 0xc1,  // UCSR0B
+            out = out(reg) out,
+            options(preserves_flags, nostack)
+        );
+        out & 0b0000_1000 != 0 // TXEN0
+    }
+}
+
+/// Enables the SPI in master mode, sending the most significant bit first, with a clock of
+/// 4MHz. Ports B3 (MOSI) and B5 (SCK) must be outputs, as well as B2 (SS), otherwise the SPI
+/// switches to slave mode when it goes low.
+pub fn enable_spi() {
+    unsafe {
+        core::arch::asm!(
+            r#"
+            sts {spsr}, {zero}
+            sts {spcr}, {spcr_val}
+            "#,
+            spcr = const 0x4c, spsr = const 0x4d,
+            zero = in(reg_upper) 0u8,
+            spcr_val = in(reg) 0b0101_0000u8,   // SPE, MSTR
+            options(preserves_flags, nostack)
+        );
+    }
+}
+
+/// Sends a byte through the SPI, and waits for it to be sent.
+pub fn write_spi(byte: u8) {
+    unsafe {
+        core::arch::asm!(
+            r#"
+            sts {spdr}, {byte}
+        1:
+            lds {tmp}, {spsr}
+            sbrs {tmp}, 7       // SPIF is set at the end of the transfer
+            rjmp 1b
+            "#,
+            spsr = const 0x4d, spdr = const 0x4e,
+            byte = in(reg) byte,
+            tmp = out(reg_upper) _,
+            options(nostack)
+        );
+    }
+}
+
+/// Returns the current value of the counter of timer0.
+pub fn read_timer0() -> u8 {
+    unsafe {
+        let out: u8;
+        core::arch::asm!(
+            "lds {out}, {addr}",
+            addr = const 0x46,
+            out = out(reg_upper) out,
+            options(preserves_flags, nostack)
+        );
+        out
+    }
+}
+
+/// Returns `true` if timer0 has overflowed since its interrupt handler last ran, which happens
+/// when interrupts are disabled.
+pub fn is_timer0_overflow_pending() -> bool {
+    unsafe {
+        let out: u8;
+        core::arch::asm!(
+            "lds {out}, {addr}",
+            addr = const 0x35,
+            out = out(reg_upper) out,
+            options(preserves_flags, nostack)
+        );
+        // Bit 0 of TIFR0 is TOV0.
+        out & 0b1 != 0
+    }
+}
+
+/// Returns the flags of `MCUSR`, which tell what caused the last reset, and clears them.
+pub fn take_reset_flags() -> u8 {
+    unsafe {
+        let out: u8;
+        core::arch::asm!(
+            r#"
+            lds {out}, {mcusr}
+            sts {mcusr}, {zero}
+            "#,
+            mcusr = const 0x54,
+            out = out(reg) out,
+            zero = in(reg_upper) 0u8,
+            options(preserves_flags, nostack)
+        );
+        out
+    }
+}
+
+/// Enables the watchdog in interrupt and system reset mode, with a timeout of 2 seconds. The
+/// first timeout triggers the `WDT` interrupt, and the second one resets the MCU.
+pub fn enable_watchdog() {
+    unsafe {
+        core::arch::asm!(
+            r#"
+            lds {sreg}, 0x5f    // SREG
+            cli
+            wdr
+            // The configuration must be written at most 4 cycles after setting WDCE.
+            sts {wdtcsr}, {change_enable}
+            sts {wdtcsr}, {config}
+            sts 0x5f, {sreg}    // SREG
+            "#,
+            wdtcsr = const 0x60,
+            change_enable = in(reg) 0b0001_1000u8,  // WDCE, WDE
+            config = in(reg) 0b0100_1111u8,         // WDIE, WDE, WDP2..0
+            sreg = out(reg) _,
+            options(nostack)
+        );
+    }
+}
+
+/// Restarts the timeout of the watchdog. Also enables the `WDT` interrupt again if it has been
+/// triggered, which disables it.
+pub fn feed_watchdog() {
+    unsafe {
+        core::arch::asm!(
+            r#"
+            wdr
+            lds {tmp}, {wdtcsr}
+            ori {tmp}, 0x40     // WDIE
+            sts {wdtcsr}, {tmp}
+            "#,
+            wdtcsr = const 0x60,
+            tmp = out(reg_upper) _,
+            options(nostack)
+        );
+    }
+}
+
+/// Disables the `WDT` interrupt, so that the next timeout of the watchdog resets the MCU right
+/// away.
+pub fn disable_watchdog_interrupt() {
+    unsafe {
+        core::arch::asm!(
+            r#"
+            lds {tmp}, {wdtcsr}
+            andi {tmp}, 0xbf    // WDIE
+            sts {wdtcsr}, {tmp}
+            "#,
+            wdtcsr = const 0x60,
+            tmp = out(reg_upper) _,
+            options(nostack)
+        );
+    }
+}
+
+/// Disables interrupts, until they are enabled again.
+pub fn disable_interrupts() {
+    unsafe {
+        core::arch::asm!("cli", options(nostack));
+    }
+}
+
+/// Waits for the given number of milliseconds by counting cycles, without relying on timers or
+/// interrupts.
+pub fn busy_wait_ms(ms: u16) {
+    for _ in 0..ms {
+        unsafe {
+            // Each iteration takes 4 cycles, and there are 16000 cycles per millisecond.
+            core::arch::asm!(
+                r#"
+            1:
+                sbiw {count}, 1
+                brne 1b
+                "#,
+                count = inout(reg_iw) 4000u16 => _,
+                options(nomem, nostack)
+            );
+        }
+    }
+}
+
+/// Runs the given closure with interrupts disabled, then restores the previous state of the
+/// interrupts.
+pub fn without_interrupts<T>(f: impl FnOnce() -> T) -> T {
+    unsafe {
+        let sreg: u8;
+        core::arch::asm!(
+            "lds {sreg}, 0x5f  // SREG",
+            "cli",
+            sreg = out(reg_upper) sreg,
+            options(nostack)
+        );
+        let out = f();
+        core::arch::asm!(
+            "sts 0x5f, {sreg}  // SREG",
+            sreg = in(reg_upper) sreg,
+            options(nostack)
+        );
+        out
+    }
+}
+
+/// Number of CPU cycles [`upload_bport_data`] takes to send a byte.
+pub const UPLOAD_CYCLES_PER_BYTE: u32 = 7 * 23 + 27;
+
+/// Sends the given colors to the given PIN of port B. Each color is sent in the order of its
+/// bytes, which makes it suitable for both the 3 bytes per LED of WS2811 strips and the 4 bytes
+/// per LED of SK6812 RGBW strips. Each byte is scaled by `brightness` as it is sent, the same way
+/// as [`crate::color::scale8`] does, so that the colors don't need to be modified beforehand.
+///
+/// Both use the same timings. A 0 bit is high for 312.5ns and a 1 bit for 687.5ns, and each bit
+/// lasts 1437.5ns, or 1687.5ns for the last bit of a byte. The SK6812 datasheet requires 0.3µs
+/// ±0.15µs for a 0 bit, 0.6µs ±0.15µs for a 1 bit, and 1.25µs ±0.6µs per bit. SK6812 strips only
+/// show the data once the line has stayed low for more than 80µs, and WS2811 strips 280µs, see
+/// [`crate::frame_rate::FrameLimiter`].
+pub fn upload_bport_data<const PIN: usize, const N: usize>(input_data: &[[u8; N]], brightness: u8) {
+    let num_bytes = input_data.len() * N;
+
     unsafe {
         // See <http://ww1.microchip.com/downloads/en/devicedoc/atmel-0856-avr-instruction-set-manual.pdf>
         // and <https://github.com/rust-lang/rust/blob/263d8682d6e01bb02727b15b1c72ffabc0e7396b/compiler/rustc_target/src/asm/avr.rs>
@@ -91,25 +476,23 @@ pub fn upload_bport_data<const PIN: usize>(input_data: &[u8]) {
 
             1:
                 // T= 14
-                subi {nbytes_low}, 1    // 1 cycle
-                brcs 2f
+                sbiw {nbytes}, 1        // 2 cycles
+                brcs 4f                 // 1 cycle if condition is false
 
-                // T= 16
-                nop
-                rjmp 3f
-
-            2:
                 // T= 17
-                subi {nbytes_high}, 1
-                brcs 4f
-
-            3:
-                // T= 19
                 ldi {nbits}, 8          // 1 cycle
-                ld {val}, X+            // 1 cycle
+                ld {val}, X+            // 2 cycles
 
+                // T= 20
+                // `val` = (`val` * `brightness` + `val`) >> 8
+                mul {val}, {brightness} // 2 cycles, r1:r0 = `val` * `brightness`
+                add r0, {val}           // 1 cycle
+                adc r1, {zero}          // 1 cycle
+                mov {val}, r1           // 1 cycle
+
+                // T= 25
                 rjmp 0b
-                // We jump back to 0 at T= 23 (1437.5ns)
+                // We jump back to 0 at T= 27 (1687.5ns)
 
 
             4:
@@ -124,21 +507,24 @@ pub fn upload_bport_data<const PIN: usize>(input_data: &[u8]) {
                 nop
                 nop
 
-                // Trailer to restore the SREG value.
+                // Trailer to restore the zero register used by the compiler and the SREG
+                // value.
+                clr r1
                 sts 0x5f, {sreg}     // SREG
 
             "#,
             addr = const 0x5, pin = const PIN,
 
-            nbytes_low = inout(reg_upper) u8::try_from(input_data.len() & 0xff).unwrap() => _,
-            nbytes_high = inout(reg_upper) u8::try_from((input_data.len() >> 8) & 0xff).unwrap() => _,
+            nbytes = inout(reg_iw) u16::try_from(num_bytes).unwrap() => _,
+            brightness = in(reg_upper) brightness,
+            zero = in(reg_upper) 0u8,
 
             // Temporary registers.
             nbits = out(reg_upper) _,
             sreg = out(reg_upper) _,
             val = out(reg_upper) _,
 
-            inout("X") input_data.as_ptr() => _,
+            inout("X") input_data.as_ptr() as *const u8 => _,
 
             options(preserves_flags, nostack)
         );
@@ -0,0 +1,435 @@
+// Decoding of infrared remote controls that use the NEC protocol.
+// See <https://techdocs.altium.com/display/FPGA/NEC+Infrared+Transmission+Protocol>
+//
+// The receiver module (e.g. a TSOP38238 or a VS1838B) demodulates the 38kHz carrier for us. Its
+// output is high when idle and low while a burst of carrier (a "mark") is being received.
+// A frame consists of:
+//
+// - A 9ms leading mark followed by a 4.5ms space.
+// - 32 bits, least significant bit first. Each bit is a 562.5µs mark followed by either a 562.5µs
+//   space (`0`) or a 1687.5µs space (`1`). The bytes are: address, inverted address (or high
+//   byte of the address for remotes using extended addresses), command, inverted command.
+// - A final 562.5µs mark.
+//
+// While a key is held down, the remote then sends a repeat code every 108ms, which is a 9ms mark
+// followed by a 2.25ms space and a 562.5µs mark.
+
+use crate::{leds, params::FireplaceParams, progmem::progmem};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A key of the remote has been pressed.
+    Key { address: u16, command: u8 },
+    /// The key that was last pressed is still being held down.
+    Repeat,
+}
+
+/// State machine that turns the edges of the output of the infrared receiver into [`Event`]s.
+#[derive(Debug, Clone)]
+pub struct NecDecoder {
+    last_edge_us: u32,
+    state: State,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum State {
+    /// Waiting for a leading mark.
+    Idle,
+    /// Leading mark received, waiting for the space that follows it.
+    Leader,
+    /// Waiting for the mark that precedes the bit number `bits`, or for the final mark if `bits`
+    /// is equal to 32.
+    DataMark { bits: u8, value: u32 },
+    /// Waiting for the space that encodes the bit number `bits`.
+    DataSpace { bits: u8, value: u32 },
+    /// Space of a repeat code received, waiting for the final mark.
+    RepeatMark,
+}
+
+// Tolerances are rather large, as the receiver modules tend to stretch marks by up to 100µs.
+// The minimum length of the leading mark is particularly low: the LEDs upload disables interrupts
+// for several milliseconds, and if the leading mark starts during an upload then we only notice
+// it late. See `NecDecoder::is_receiving`.
+const LEADER_MARK_US: (u32, u32) = (2_000, 10_000);
+const LEADER_SPACE_US: (u32, u32) = (4_000, 5_000);
+const REPEAT_SPACE_US: (u32, u32) = (1_800, 2_700);
+const BIT_MARK_US: (u32, u32) = (350, 800);
+const ZERO_SPACE_US: (u32, u32) = (350, 800);
+const ONE_SPACE_US: (u32, u32) = (1_400, 1_950);
+
+/// If no edge has been received for this long, the frame being received is considered aborted.
+const FRAME_TIMEOUT_US: u32 = 15_000;
+
+impl NecDecoder {
+    pub const fn new() -> Self {
+        NecDecoder {
+            last_edge_us: 0,
+            state: State::Idle,
+        }
+    }
+
+    /// Must be called whenever the output of the infrared receiver changes. `now_us` is the
+    /// current value of a clock in microseconds, which is allowed to wrap around. `pin_high` is
+    /// the new level of the pin.
+    pub fn on_edge(&mut self, now_us: u32, pin_high: bool) -> Option<Event> {
+        let duration_us = now_us.wrapping_sub(self.last_edge_us);
+        self.last_edge_us = now_us;
+        // The output of the receiver is low during marks, so the pulse that has just finished
+        // was a mark if the pin is now high.
+        self.on_pulse(pin_high, duration_us)
+    }
+
+    /// Feeds a pulse, either a mark or a space, of the given duration to the state machine.
+    pub fn on_pulse(&mut self, mark: bool, duration_us: u32) -> Option<Event> {
+        let within = |(min, max): (u32, u32)| duration_us >= min && duration_us <= max;
+
+        let mut event = None;
+        self.state = match (self.state, mark) {
+            (_, true) if within(LEADER_MARK_US) => State::Leader,
+            (State::Leader, false) if within(LEADER_SPACE_US) => {
+                State::DataMark { bits: 0, value: 0 }
+            }
+            (State::Leader, false) if within(REPEAT_SPACE_US) => State::RepeatMark,
+            (State::RepeatMark, true) if within(BIT_MARK_US) => {
+                event = Some(Event::Repeat);
+                State::Idle
+            }
+            (State::DataMark { bits: 32, value }, true) if within(BIT_MARK_US) => {
+                event = decode_frame(value);
+                State::Idle
+            }
+            (State::DataMark { bits, value }, true) if within(BIT_MARK_US) => {
+                State::DataSpace { bits, value }
+            }
+            (State::DataSpace { bits, value }, false) if within(ZERO_SPACE_US) => State::DataMark {
+                bits: bits + 1,
+                value,
+            },
+            (State::DataSpace { bits, value }, false) if within(ONE_SPACE_US) => State::DataMark {
+                bits: bits + 1,
+                value: value | (1 << bits),
+            },
+            _ => State::Idle,
+        };
+
+        event
+    }
+
+    /// Returns `true` if a frame is currently being received.
+    ///
+    /// Uploading data to the LEDs disables interrupts for long enough to corrupt the frame, and
+    /// thus shouldn't be done while this returns `true`.
+    pub fn is_receiving(&self, now_us: u32) -> bool {
+        !matches!(self.state, State::Idle)
+            && now_us.wrapping_sub(self.last_edge_us) < FRAME_TIMEOUT_US
+    }
+}
+
+fn decode_frame(value: u32) -> Option<Event> {
+    let [address_low, address_high, command, command_inverted] = value.to_le_bytes();
+    if command != !command_inverted {
+        return None;
+    }
+
+    // Remotes using 8-bit addresses send the inverted address as second byte, while others use
+    // the two bytes as a 16-bit address.
+    let address = if address_high == !address_low {
+        u16::from(address_low)
+    } else {
+        u16::from_le_bytes([address_low, address_high])
+    };
+
+    Some(Event::Key { address, command })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    SelectMode(leds::Mode),
+    /// Selects [`leds::Mode::Fireplace`] with the given parameters.
+    Fireplace(FireplaceParams),
+    BrightnessUp,
+    BrightnessDown,
+    SpeedUp,
+    SpeedDown,
+    /// Increases the saturation of the current mode, if it is a rainbow mode.
+    SaturationUp,
+    /// Decreases the saturation of the current mode, if it is a rainbow mode.
+    SaturationDown,
+    Off,
+}
+
+impl Action {
+    /// Returns `true` if the action should be performed again when the key is held down.
+    pub fn repeats(&self) -> bool {
+        matches!(
+            self,
+            Action::BrightnessUp
+                | Action::BrightnessDown
+                | Action::SpeedUp
+                | Action::SpeedDown
+                | Action::SaturationUp
+                | Action::SaturationDown
+        )
+    }
+}
+
+/// Address of the remote to listen to. Frames sent with a different address are ignored.
+///
+/// The common 24 keys remotes use the extended address 0xEF00. Their codes are usually written
+/// with the bits in the order they are received, such as 0xF700FF for brightness up.
+pub const REMOTE_ADDRESS: u16 = 0xef00;
+
+progmem! {
+    /// Mapping between commands of the remote and actions.
+    ///
+    /// The default values correspond to the common 24 keys remotes sold together with RGB LED
+    /// strips. The top rows are brightness up, brightness down, off, on, then red, green, blue,
+    /// white, and the right-most column is flash, strobe, fade, smooth. The two keys below red are
+    /// used to change the speed of the current mode, the two keys below green its saturation, and
+    /// the other keys select the other modes.
+    pub static KEY_MAP: [(u8, Action); 24] = [
+        (0x00, Action::BrightnessUp),
+        (0x01, Action::BrightnessDown),
+        (0x02, Action::Off),
+        (0x03, Action::SelectMode(leds::Mode::Neutral)),
+        (0x04, Action::Fireplace(FireplaceParams::ORANGE)),
+        (0x05, Action::SelectMode(leds::Mode::Fire)),
+        (0x06, Action::Fireplace(FireplaceParams::BLUE)),
+        (0x07, Action::SelectMode(leds::Mode::Neutral)),
+        (0x08, Action::SpeedUp),
+        (0x09, Action::SaturationUp),
+        (0x0a, Action::SelectMode(leds::Mode::Rainbow)),
+        (0x0b, Action::SelectMode(leds::Mode::PartyCycle)),
+        (0x0c, Action::SpeedDown),
+        (0x0d, Action::SaturationDown),
+        (0x0e, Action::SelectMode(leds::Mode::RainbowGlitter)),
+        (0x0f, Action::SelectMode(leds::Mode::SegmentLights)),
+        (0x10, Action::SelectMode(leds::Mode::SideRotation)),
+        (0x11, Action::SelectMode(leds::Mode::SlowDrift)),
+        (0x12, Action::SelectMode(leds::Mode::Cursor)),
+        (0x13, Action::SelectMode(leds::Mode::WholeStripAlternatingColor)),
+        (0x14, Action::SelectMode(leds::Mode::Strobe)),
+        (0x15, Action::SelectMode(leds::Mode::TheatreChase)),
+        (0x16, Action::SelectMode(leds::Mode::HueCycle)),
+        (0x17, Action::SelectMode(leds::Mode::Music)),
+    ];
+}
+
+/// Returns the action corresponding to the given key, if any.
+pub fn action_for_key(address: u16, command: u8) -> Option<Action> {
+    if address != REMOTE_ADDRESS {
+        return None;
+    }
+
+    KEY_MAP
+        .iter()
+        .find(|(c, _)| *c == command)
+        .map(|(_, action)| action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the pulses of a frame carrying the given 32 bits, as `(mark, duration_us)`.
+    fn frame_pulses(value: u32) -> Vec<(bool, u32)> {
+        let mut pulses = vec![(true, 9_000), (false, 4_500)];
+        for bit in 0..32 {
+            pulses.push((true, 563));
+            pulses.push((false, if value & (1 << bit) != 0 { 1_688 } else { 563 }));
+        }
+        pulses.push((true, 563));
+        pulses
+    }
+
+    const REPEAT_PULSES: [(bool, u32); 3] = [(true, 9_000), (false, 2_250), (true, 563)];
+
+    /// Feeds the given pulses to a decoder as edges of the output of the receiver, starting at
+    /// `start_us`, and returns the events it produced.
+    fn decode(decoder: &mut NecDecoder, start_us: u32, pulses: &[(bool, u32)]) -> Vec<Event> {
+        let mut now_us = start_us;
+        // Falling edge starting the first mark.
+        decoder.on_edge(now_us, false);
+        pulses
+            .iter()
+            .filter_map(|&(mark, duration_us)| {
+                now_us = now_us.wrapping_add(duration_us);
+                decoder.on_edge(now_us, mark)
+            })
+            .collect()
+    }
+
+    fn key_frame(address: u8, command: u8) -> u32 {
+        u32::from_le_bytes([address, !address, command, !command])
+    }
+
+    #[test]
+    fn key_and_repeats() {
+        let mut decoder = NecDecoder::new();
+        let mut pulses = frame_pulses(key_frame(0x00, 0x0b));
+        pulses.push((false, 40_000));
+        pulses.extend(REPEAT_PULSES);
+        pulses.push((false, 96_000));
+        pulses.extend(REPEAT_PULSES);
+        assert_eq!(
+            decode(&mut decoder, 1_000, &pulses),
+            [
+                Event::Key {
+                    address: 0x00,
+                    command: 0x0b
+                },
+                Event::Repeat,
+                Event::Repeat
+            ]
+        );
+    }
+
+    #[test]
+    fn extended_address() {
+        let mut decoder = NecDecoder::new();
+        let value = u32::from_le_bytes([0x34, 0x12, 0x05, !0x05]);
+        assert_eq!(
+            decode(&mut decoder, 0, &frame_pulses(value)),
+            [Event::Key {
+                address: 0x1234,
+                command: 0x05
+            }]
+        );
+    }
+
+    #[test]
+    fn corrupted_command() {
+        let mut decoder = NecDecoder::new();
+        let value = u32::from_le_bytes([0x00, 0xff, 0x05, 0x05]);
+        assert_eq!(decode(&mut decoder, 0, &frame_pulses(value)), []);
+    }
+
+    #[test]
+    fn stretched_marks() {
+        // The receiver modules stretch the marks, and shorten the spaces by the same amount.
+        let mut decoder = NecDecoder::new();
+        let pulses: Vec<_> = frame_pulses(key_frame(0x00, 0x02))
+            .into_iter()
+            .map(|(mark, duration_us)| {
+                if mark {
+                    (mark, duration_us + 100)
+                } else {
+                    (mark, duration_us - 100)
+                }
+            })
+            .collect();
+        assert_eq!(
+            decode(&mut decoder, 0, &pulses),
+            [Event::Key {
+                address: 0x00,
+                command: 0x02
+            }]
+        );
+    }
+
+    #[test]
+    fn leader_noticed_late() {
+        // The leading mark started while interrupts were disabled by an upload to the LEDs.
+        let mut decoder = NecDecoder::new();
+        let mut pulses = frame_pulses(key_frame(0x00, 0x03));
+        pulses[0].1 = 3_000;
+        assert_eq!(
+            decode(&mut decoder, 0, &pulses),
+            [Event::Key {
+                address: 0x00,
+                command: 0x03
+            }]
+        );
+    }
+
+    #[test]
+    fn missing_bit() {
+        // A frame with a bit missing followed by a repeat code must not produce a key.
+        let mut decoder = NecDecoder::new();
+        let mut pulses = frame_pulses(key_frame(0x00, 0x04));
+        pulses.drain(2..4);
+        pulses.push((false, 40_000));
+        pulses.extend(REPEAT_PULSES);
+        assert_eq!(decode(&mut decoder, 0, &pulses), [Event::Repeat]);
+    }
+
+    #[test]
+    fn clock_wrapping_around() {
+        let mut decoder = NecDecoder::new();
+        assert_eq!(
+            decode(
+                &mut decoder,
+                u32::MAX - 20_000,
+                &frame_pulses(key_frame(0x00, 0x17))
+            ),
+            [Event::Key {
+                address: 0x00,
+                command: 0x17
+            }]
+        );
+    }
+
+    #[test]
+    fn is_receiving() {
+        let mut decoder = NecDecoder::new();
+        assert!(!decoder.is_receiving(0));
+        decoder.on_edge(1_000, false);
+        decoder.on_edge(10_000, true);
+        assert!(decoder.is_receiving(12_000));
+        // The frame is considered aborted once no edge has been received for too long.
+        assert!(!decoder.is_receiving(10_000 + FRAME_TIMEOUT_US));
+        decoder.on_edge(14_500, false);
+        assert!(decoder.is_receiving(15_000));
+    }
+
+    #[test]
+    fn remote_24_keys() {
+        // See `testdata/ir/generate.py`. The keys are in the order of their commands.
+        let lines = include_str!("../testdata/ir/remote_24_keys.txt").lines();
+        for (line, command) in lines.zip(0..) {
+            let mut words = line.split(' ');
+            let name = words.next().unwrap();
+            let pulses: Vec<_> = words
+                .map(|word| {
+                    let duration_us: i32 = word.parse().unwrap();
+                    (duration_us > 0, duration_us.unsigned_abs())
+                })
+                .collect();
+            let mut decoder = NecDecoder::new();
+            assert_eq!(
+                decode(&mut decoder, 0, &pulses),
+                [
+                    Event::Key {
+                        address: REMOTE_ADDRESS,
+                        command
+                    },
+                    Event::Repeat
+                ],
+                "{name}"
+            );
+            assert!(action_for_key(REMOTE_ADDRESS, command).is_some(), "{name}");
+        }
+    }
+
+    #[test]
+    fn actions() {
+        assert_eq!(
+            action_for_key(REMOTE_ADDRESS, 0x00),
+            Some(Action::BrightnessUp)
+        );
+        assert_eq!(action_for_key(REMOTE_ADDRESS, 0x02), Some(Action::Off));
+        assert_eq!(
+            action_for_key(REMOTE_ADDRESS, 0x0a),
+            Some(Action::SelectMode(leds::Mode::Rainbow))
+        );
+        assert_eq!(action_for_key(REMOTE_ADDRESS, 0x18), None);
+        assert_eq!(action_for_key(REMOTE_ADDRESS + 1, 0x0a), None);
+
+        // Only adjustments repeat when a key is held down.
+        assert!(Action::BrightnessUp.repeats());
+        assert!(Action::SpeedDown.repeats());
+        assert!(!Action::Off.repeats());
+        assert!(!Action::SelectMode(leds::Mode::Rainbow).repeats());
+        assert!(!Action::Fireplace(FireplaceParams::ORANGE).repeats());
+    }
+}
@@ -0,0 +1,458 @@
+// Effects that can be stacked on top of each other.
+//
+// A scene is a list of layers. Each layer applies an effect to the colors produced by the layers
+// below it, and the result is then blended with these colors. Effects that generate colors, such
+// as `Effect::Color`, ignore the colors below them, while the others modify them.
+
+use core::{cmp, time::Duration};
+
+use crate::{
+    clock,
+    color::{self, Rgb},
+    leds::{self, Wall, NORTH_LEDS, SIN_TABLE, SOUTH_LEDS, TOTAL_LEDS},
+    pattern::{Frame, FrameContext, ModeState, Pattern},
+};
+
+pub const SIDE_ROTATION: &[Layer] = &[Layer {
+    effect: Effect::SideRotation,
+    blend: Blend::Replace,
+    opacity: 255,
+}];
+
+pub const SLOW_DRIFT: &[Layer] = &[
+    Layer {
+        effect: Effect::SlowlyChangingColor,
+        blend: Blend::Replace,
+        opacity: 255,
+    },
+    Layer {
+        effect: Effect::Wave {
+            num_periods: 3,
+            angle_step_ms: 20,
+        },
+        blend: Blend::Replace,
+        opacity: 255,
+    },
+];
+
+pub const CURSOR: &[Layer] = &[
+    Layer {
+        effect: Effect::SlowlyChangingColor,
+        blend: Blend::Replace,
+        opacity: 255,
+    },
+    Layer {
+        effect: Effect::Gradient,
+        blend: Blend::Replace,
+        opacity: 255,
+    },
+    Layer {
+        effect: Effect::Cursor(Rgb::WHITE),
+        blend: Blend::Screen,
+        opacity: 255,
+    },
+];
+
+pub const STROBE: &[Layer] = &[
+    Layer {
+        effect: Effect::SlowlyChangingColor,
+        blend: Blend::Replace,
+        opacity: 255,
+    },
+    // The color is dimmed so that the flashes are visible.
+    Layer {
+        effect: Effect::Color(Rgb::new(64, 64, 64)),
+        blend: Blend::Multiply,
+        opacity: 255,
+    },
+    Layer {
+        effect: Effect::Flashing,
+        blend: Blend::Add,
+        opacity: 255,
+    },
+    // Last, as the flashes would light up black LEDs.
+    Layer {
+        effect: Effect::OnOffPeriodic,
+        blend: Blend::Replace,
+        opacity: 255,
+    },
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Layer {
+    pub effect: Effect,
+    /// How the output of the effect is combined with the colors below.
+    pub blend: Blend,
+    /// Opacity of the output of the effect, where 0 means that the layer has no effect.
+    pub opacity: u8,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Effect {
+    /// Same color on all the LEDs.
+    Color(Rgb),
+    /// Color going through a list of colors, each transition taking a minute.
+    SlowlyChangingColor,
+    /// Each wall has a different color, and the colors rotate around the room every 2 seconds.
+    SideRotation,
+    /// Modulates the brightness with a sine wave that goes around the room `num_periods` times,
+    /// and moves by 1/256th of its period every `angle_step_ms` milliseconds.
+    Wave {
+        num_periods: u16,
+        angle_step_ms: u16,
+    },
+    /// Single LED of the given color going around the room, moving every 500ms. The other LEDs
+    /// are left untouched.
+    Cursor(Rgb),
+    /// Makes the colors fade towards the west side of the room.
+    Gradient,
+    /// Every other frame, 5/4 of the colors below, to be added to them so that they are brighter.
+    /// Never fully black, so that black LEDs also flash.
+    Flashing,
+    /// Switches the LEDs off for 3 seconds every 6 seconds.
+    OnOffPeriodic,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Blend {
+    /// Output of the effect replaces the colors below.
+    Replace,
+    /// Output of the effect is added to the colors below.
+    Add,
+    /// Colors below are multiplied by the output of the effect, where 255 is 1.
+    Multiply,
+    /// Inverse of multiplying the inverse of the colors, which brightens the colors below.
+    Screen,
+}
+
+impl Blend {
+    fn apply(&self, below: u8, above: u8) -> u8 {
+        match self {
+            Blend::Replace => above,
+            Blend::Add => below.saturating_add(above),
+            Blend::Multiply => color::scale8(below, above),
+            Blend::Screen => 255 - ((u16::from(255 - below) * u16::from(255 - above)) / 255) as u8,
+        }
+    }
+}
+
+/// Pattern that renders the given layers on top of black.
+pub struct Scene(pub &'static [Layer]);
+
+impl Pattern for Scene {
+    fn render(&self, ctx: &FrameContext, _: &mut ModeState, frame: &mut Frame) {
+        frame.fill(Rgb::BLACK);
+        apply(self.0, ctx, frame);
+    }
+}
+
+/// Applies the given layers on top of the colors in `frame`.
+pub fn apply(layers: &[Layer], ctx: &FrameContext, frame: &mut Frame) {
+    for layer in layers {
+        // The parts of the effects that only depend on the clock are calculated only once here,
+        // rather than for each LED.
+        let effect = layer
+            .effect
+            .prepare(ctx.clock_value, ctx.updates_wrapping_counter);
+
+        for (led_pos, color) in frame.iter_mut().enumerate() {
+            let above = match effect.apply(led_pos, *color) {
+                Some(above) => above,
+                None => continue,
+            };
+
+            let blended = color.zip_map(above, |below, above| layer.blend.apply(below, above));
+            *color = color.lerp(blended, layer.opacity);
+        }
+    }
+}
+
+impl Effect {
+    fn prepare(&self, clock_value: Duration, updates_wrapping_counter: u8) -> PreparedEffect {
+        match *self {
+            Effect::Color(color) => PreparedEffect::Color(color),
+            Effect::SlowlyChangingColor => {
+                PreparedEffect::Color(slowly_changing_color(clock_value))
+            }
+            Effect::SideRotation => PreparedEffect::SideRotation {
+                side_add: (clock::millis_in_period(clock_value, 4 * 2000) / 2000) as u16,
+            },
+            Effect::Wave {
+                num_periods,
+                angle_step_ms,
+            } => PreparedEffect::Wave {
+                num_periods,
+                angle_add: (clock::millis_in_period(clock_value, 256 * u32::from(angle_step_ms))
+                    / u32::from(angle_step_ms)) as u8,
+            },
+            Effect::Cursor(color) => PreparedEffect::Cursor {
+                color,
+                led_pos: (clock::millis_in_period(clock_value, TOTAL_LEDS as u32 * 500) / 500)
+                    as usize,
+            },
+            Effect::Gradient => PreparedEffect::Gradient,
+            Effect::Flashing => PreparedEffect::Flashing {
+                flash: updates_wrapping_counter % 2 == 0,
+            },
+            Effect::OnOffPeriodic => PreparedEffect::OnOff {
+                is_on: (clock_value.as_secs() % 6) < 3,
+            },
+        }
+    }
+}
+
+/// [`Effect`] at a specific moment in time.
+#[derive(Debug, Copy, Clone)]
+enum PreparedEffect {
+    Color(Rgb),
+    SideRotation { side_add: u16 },
+    Wave { num_periods: u16, angle_add: u8 },
+    Cursor { color: Rgb, led_pos: usize },
+    Gradient,
+    Flashing { flash: bool },
+    OnOff { is_on: bool },
+}
+
+impl PreparedEffect {
+    /// Returns the output of the effect for the LED at the given position, given the color
+    /// below. Returns `None` if the effect leaves this LED untouched.
+    fn apply(&self, led_pos: usize, below: Rgb) -> Option<Rgb> {
+        match *self {
+            PreparedEffect::Color(color) => Some(color),
+            PreparedEffect::SideRotation { side_add } => {
+                let side_num = match leds::wall(led_pos).0 {
+                    Wall::West => 0,
+                    Wall::North => 1,
+                    Wall::East => 2,
+                    Wall::South => 3,
+                };
+
+                Some(match (side_num + side_add) % 4 {
+                    0 => Rgb::new(255, 0, 0),
+                    1 => Rgb::new(128, 0, 128),
+                    2 => Rgb::new(0, 0, 255),
+                    3 => Rgb::new(0, 255, 0),
+                    _ => unreachable!(),
+                })
+            }
+            PreparedEffect::Wave {
+                num_periods,
+                angle_add,
+            } => {
+                let angle = u32::from(angle_add)
+                    + u32::from(num_periods) * 256 * led_pos as u32 / TOTAL_LEDS as u32;
+                let sin_value = i16::from(SIN_TABLE.load((angle & 0xff) as usize));
+                Some(below.map(|n| (i16::from(n) * (sin_value + 64) / 128) as u8))
+            }
+            PreparedEffect::Cursor {
+                color,
+                led_pos: cursor_pos,
+            } => {
+                if led_pos == cursor_pos {
+                    Some(color)
+                } else {
+                    None
+                }
+            }
+            PreparedEffect::Gradient => {
+                const MIN_INTENSITY: u16 = 4;
+
+                let intensity = match leds::wall(led_pos) {
+                    (Wall::West, _) => MIN_INTENSITY,
+                    (Wall::North, n) => {
+                        MIN_INTENSITY
+                            + (256 - MIN_INTENSITY) * u16::try_from(n).unwrap()
+                                / u16::try_from(NORTH_LEDS).unwrap()
+                    }
+                    (Wall::East, _) => 256,
+                    (Wall::South, n) => {
+                        MIN_INTENSITY
+                            + (256 - MIN_INTENSITY) * u16::try_from(SOUTH_LEDS - 1 - n).unwrap()
+                                / u16::try_from(SOUTH_LEDS).unwrap()
+                    }
+                };
+
+                Some(below.map(|n| (intensity * u16::from(n) / 256) as u8))
+            }
+            PreparedEffect::Flashing { flash: true } => {
+                Some(below.map(|n| cmp::max(cmp::min(u16::from(n) * 5 / 4, 255) as u8, 1)))
+            }
+            PreparedEffect::Flashing { flash: false } => None,
+            PreparedEffect::OnOff { is_on: true } => None,
+            PreparedEffect::OnOff { is_on: false } => Some(Rgb::BLACK),
+        }
+    }
+}
+
+fn slowly_changing_color(clock_value: Duration) -> Rgb {
+    const COLOR_DURATION: u32 = 60000;
+
+    let colors = [
+        Rgb::new(255, 0, 0),
+        Rgb::new(0, 128, 128),
+        Rgb::new(0, 0, 255),
+        Rgb::new(128, 128, 0),
+        Rgb::new(0, 255, 0),
+        Rgb::new(128, 0, 128),
+    ];
+
+    let step = clock::millis_in_period(clock_value, COLOR_DURATION * colors.len() as u32);
+
+    let color_from_idx = (step / COLOR_DURATION) as usize;
+    let color_from = colors[color_from_idx];
+    let color_to_idx = (color_from_idx + 1) % colors.len();
+    let color_to = colors[color_to_idx];
+
+    let progress = ((step % COLOR_DURATION) * 255 / COLOR_DURATION) as u8;
+    color_from.lerp(color_to, progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{audio::AudioFrame, random::Rng, settings::Settings};
+
+    /// Renders the given scene at the given value of the clock and frame counter.
+    fn render(layers: &'static [Layer], millis: u64, updates_wrapping_counter: u8) -> Frame {
+        let ctx = FrameContext {
+            clock_value: Duration::from_millis(millis),
+            updates_wrapping_counter,
+            settings: &Settings::new(),
+            audio: &AudioFrame::default(),
+            rng: &Rng::new(1),
+            time_of_day: None,
+        };
+        let mut frame = [Rgb::new(1, 2, 3); TOTAL_LEDS];
+        Scene(layers).render(&ctx, &mut ModeState::None, &mut frame);
+        frame
+    }
+
+    /// Returns the color of each wall, checking that all the LEDs of a wall have the same color.
+    fn wall_colors(frame: &Frame) -> [Rgb; 4] {
+        let mut colors = [None; 4];
+        for (led_pos, color) in frame.iter().enumerate() {
+            let wall = leds::wall(led_pos).0 as usize;
+            assert_eq!(*colors[wall].get_or_insert(*color), *color);
+        }
+        colors.map(Option::unwrap)
+    }
+
+    #[test]
+    fn blend() {
+        for below in [0, 1, 100, 200, 255] {
+            assert_eq!(Blend::Replace.apply(below, 42), 42);
+            // Black and white are the neutral colors of the blend modes, with which the colors
+            // below are left as they were.
+            assert_eq!(Blend::Add.apply(below, 0), below);
+            assert_eq!(Blend::Multiply.apply(below, 255), below);
+            assert_eq!(Blend::Multiply.apply(below, 0), 0);
+            assert_eq!(Blend::Screen.apply(below, 0), below);
+            assert_eq!(Blend::Screen.apply(below, 255), 255);
+        }
+        assert_eq!(Blend::Add.apply(100, 100), 200);
+        assert_eq!(Blend::Add.apply(200, 100), 255);
+        assert_eq!(Blend::Multiply.apply(200, 128), 100);
+        assert_eq!(Blend::Multiply.apply(128, 200), 100);
+        assert_eq!(Blend::Screen.apply(128, 128), 192);
+        assert_eq!(Blend::Screen.apply(100, 200), 222);
+    }
+
+    #[test]
+    fn opacity() {
+        const fn layers(opacity: u8) -> [Layer; 2] {
+            [
+                Layer {
+                    effect: Effect::Color(Rgb::new(200, 0, 100)),
+                    blend: Blend::Replace,
+                    opacity: 255,
+                },
+                Layer {
+                    effect: Effect::Color(Rgb::new(0, 200, 100)),
+                    blend: Blend::Replace,
+                    opacity,
+                },
+            ]
+        }
+        const TRANSPARENT: &[Layer] = &layers(0);
+        const HALF: &[Layer] = &layers(128);
+        const OPAQUE: &[Layer] = &layers(255);
+
+        let color = |layers| render(layers, 0, 0)[0];
+        assert_eq!(color(TRANSPARENT), Rgb::new(200, 0, 100));
+        assert_eq!(color(HALF), Rgb::new(99, 100, 100));
+        assert_eq!(color(OPAQUE), Rgb::new(0, 200, 100));
+    }
+
+    #[test]
+    fn side_rotation() {
+        let colors = wall_colors(&render(SIDE_ROTATION, 0, 0));
+        for (n, color) in colors.iter().enumerate() {
+            assert!(!colors[n + 1..].contains(color), "{colors:?}");
+        }
+        // Every 2 seconds, each wall takes the color of the next one.
+        assert_eq!(wall_colors(&render(SIDE_ROTATION, 1_999, 0)), colors);
+        let [west, north, east, south] = colors;
+        assert_eq!(
+            wall_colors(&render(SIDE_ROTATION, 2_000, 0)),
+            [north, east, south, west]
+        );
+        assert_eq!(wall_colors(&render(SIDE_ROTATION, 8_000, 0)), colors);
+    }
+
+    #[test]
+    fn slow_drift() {
+        let frame = render(SLOW_DRIFT, 10_000, 0);
+        // The wave makes the brightness vary around the room.
+        let brightness = |color: &Rgb| color.0.iter().map(|n| u16::from(*n)).sum::<u16>();
+        let min = frame.iter().map(brightness).min().unwrap();
+        let max = frame.iter().map(brightness).max().unwrap();
+        assert!(max > min + 100, "{min} {max}");
+
+        // The colors change slowly from one frame to the next.
+        let next_frame = render(SLOW_DRIFT, 10_016, 1);
+        for (color, next_color) in frame.iter().zip(&next_frame) {
+            for (n, next_n) in color.0.iter().zip(&next_color.0) {
+                assert!(n.abs_diff(*next_n) <= 8, "{color:?} {next_color:?}");
+            }
+        }
+        // But the wave does move.
+        assert_ne!(frame, render(SLOW_DRIFT, 10_100, 2));
+    }
+
+    #[test]
+    fn cursor() {
+        let cursor_positions = |millis| {
+            let frame = render(CURSOR, millis, 0);
+            (0..TOTAL_LEDS)
+                .filter(|led_pos| frame[*led_pos] == Rgb::WHITE)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(cursor_positions(0), [0]);
+        assert_eq!(cursor_positions(499), [0]);
+        assert_eq!(cursor_positions(500), [1]);
+        assert_eq!(cursor_positions(500 * 100), [100]);
+        // Back at the start after going around the room.
+        assert_eq!(cursor_positions(500 * TOTAL_LEDS as u64), [0]);
+    }
+
+    #[test]
+    fn strobe() {
+        // Switched off for 3 seconds every 6 seconds.
+        for millis in [3_000, 5_999, 9_000] {
+            assert!(render(STROBE, millis, 0)
+                .iter()
+                .all(|color| *color == Rgb::BLACK));
+        }
+        // Otherwise flashing every other frame.
+        for millis in [0, 2_999, 6_000] {
+            let flash = render(STROBE, millis, 0);
+            let dim = render(STROBE, millis, 1);
+            for (flash, dim) in flash.iter().zip(&dim) {
+                assert!(*dim != Rgb::BLACK);
+                for (flash, dim) in flash.0.iter().zip(&dim.0) {
+                    assert_eq!(u16::from(*flash), (u16::from(*dim) * 9 / 4).clamp(1, 255));
+                }
+            }
+        }
+    }
+}
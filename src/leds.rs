@@ -1,19 +1,63 @@
-use core::{cmp, iter, time::Duration};
+use core::{cmp, time::Duration};
+
+use crate::{
+    clock,
+    color::{Hsv, Rgb},
+    output::LedType,
+    pattern::{Frame, FrameContext, ModeState, Pattern},
+    progmem::progmem,
+};
 
 pub const TOTAL_LEDS: usize = WEST_LEDS + NORTH_LEDS + SOUTH_LEDS + EAST_LEDS;
-const WEST_LEDS: usize = 22;
-const NORTH_LEDS: usize = 62;
-const SOUTH_LEDS: usize = 64; // Note: it's actually 64.5, as the corner cuts it in half, a bit annoying
-const EAST_LEDS: usize = 25;
+pub const WEST_LEDS: usize = 22;
+pub const NORTH_LEDS: usize = 62;
+pub const SOUTH_LEDS: usize = 64; // Note: it's actually 64.5, as the corner cuts it in half, a bit annoying
+pub const EAST_LEDS: usize = 25;
+
+/// Color which looks roughly white on the WS2811 strips.
+pub const NEUTRAL_WHITE: Rgb = Rgb::new(140, 50, 20);
 
+/// Mode currently being displayed. See also [`crate::pattern::MODES`].
+///
+/// The discriminants are the identifiers of the modes returned by [`Mode::id`], which are stored
+/// and must stay the same across versions: never reorder or reuse them, and give new modes the
+/// next free value.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
 pub enum Mode {
-    Off,
-    Neutral,
-    Fireplace,
-    SegmentLights,
-    WholeStripAlternatingColor,
-    PartyCycle,
+    Off = 0,
+    Neutral = 1,
+    Fireplace = 2,
+    SegmentLights = 3,
+    WholeStripAlternatingColor = 4,
+    PartyCycle = 5,
+    Music = 6,
+    /// Each wall has a different color, and the colors rotate around the room.
+    SideRotation = 7,
+    /// Slowly changing color, with waves of brightness going through the room.
+    SlowDrift = 8,
+    /// Slowly changing color getting brighter towards the east, with a cursor going around the
+    /// room.
+    Cursor = 9,
+    /// Slowly changing color flickering quickly, switching off periodically.
+    Strobe = 10,
+    /// Rainbow going around the room.
+    Rainbow = 11,
+    /// Same as [`Mode::Rainbow`], with white sparkles appearing at random positions.
+    RainbowGlitter = 12,
+    /// One LED out of three lit with the colors of a rainbow, the lit LEDs moving around the
+    /// room like on theatre marquees.
+    TheatreChase = 13,
+    /// Whole room of a single color, slowly going through all the hues.
+    HueCycle = 14,
+    /// Simulation of flames rising from the corners of the room.
+    Fire = 15,
+    /// LEDs randomly brightening and fading over a dim color, like stars.
+    Twinkle = 16,
+    /// Comets going around the room, followed by fading trails.
+    Meteor = 17,
+    /// Room brightening from a dim red to a warm white until the wake-up time.
+    Sunrise = 18,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -22,444 +66,338 @@ pub enum Strip {
     SouthEast,
 }
 
-pub fn led_colors_lerp(
-    mode1: Mode,
-    mode2: Mode,
-    since_mode_change: Duration,
-    clock_value: Duration,
-    updates_wrapping_counter: u8,
-    strip: Strip,
-) -> impl Iterator<Item = [u8; 3]> + Clone {
-    const LERP_DURATION_MS: u32 = 1000;
-
-    let mode2_weight = u8::try_from(cmp::min(
-        u32::try_from(since_mode_change.as_millis())
-            .unwrap_or(u32::max_value())
-            .saturating_mul(255)
-            / LERP_DURATION_MS,
-        255,
-    ))
-    .unwrap();
-
-    let colors1 = led_colors(mode1, clock_value, updates_wrapping_counter, strip);
-    let colors2 = led_colors(mode2, clock_value, updates_wrapping_counter, strip);
-
-    colors1.zip(colors2).map(move |(c1, c2)| {
-        fn avg(a: u8, b: u8, b_weight: u8) -> u8 {
-            let res = u16::from(a) * u16::from(255 - b_weight) + u16::from(b) * u16::from(b_weight);
-            u8::try_from(res / 255).unwrap()
+impl Strip {
+    /// Returns the number of LEDs of this strip.
+    pub const fn num_leds(self) -> usize {
+        match self {
+            Strip::NorthWest => NORTH_LEDS + WEST_LEDS,
+            Strip::SouthEast => SOUTH_LEDS + EAST_LEDS,
         }
-        [
-            avg(c1[0], c2[0], mode2_weight),
-            avg(c1[1], c2[1], mode2_weight),
-            avg(c1[2], c2[2], mode2_weight),
-        ]
-    })
+    }
+
+    /// Returns the type of the LEDs of this strip.
+    pub const fn led_type(self) -> LedType {
+        match self {
+            Strip::NorthWest => LedType::Ws2811,
+            Strip::SouthEast => LedType::Ws2811,
+        }
+    }
 }
 
-pub fn led_colors(
-    mut mode: Mode,
-    clock_value: Duration,
-    updates_wrapping_counter: u8,
-    strip: Strip,
-) -> impl Iterator<Item = [u8; 3]> + Clone {
-    macro_rules! gen {
-        ($($n:ident),*) => {
-            #[derive(Clone)]
-            enum ModeIter<$($n),*> {
-                $($n($n),)*
-            }
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Wall {
+    West,
+    North,
+    East,
+    South,
+}
 
-            impl<
-                    $($n: Iterator<Item = [u8; 3]>,)*
-                > Iterator for ModeIter<$($n),*>
-            {
-                type Item = [u8; 3];
+/// Returns the wall of the LED at the given position in the frame, and the position of the LED
+/// relative to the start of that wall.
+///
+/// Frames are in layout order: positions go around the room, starting with the west wall, then
+/// north, east, and south. The north-west strip starts at position 0, while the south-east strip
+/// is plugged at the other end of the room, and its LEDs are thus in the reverse order.
+pub fn wall(pos: usize) -> (Wall, usize) {
+    debug_assert!(pos < TOTAL_LEDS);
+    if pos < WEST_LEDS {
+        (Wall::West, pos)
+    } else if pos < WEST_LEDS + NORTH_LEDS {
+        (Wall::North, pos - WEST_LEDS)
+    } else if pos < WEST_LEDS + NORTH_LEDS + EAST_LEDS {
+        (Wall::East, pos - WEST_LEDS - NORTH_LEDS)
+    } else {
+        (Wall::South, pos - WEST_LEDS - NORTH_LEDS - EAST_LEDS)
+    }
+}
 
-                fn next(&mut self) -> Option<Self::Item> {
-                    match self {
-                        $(ModeIter::$n(i) => i.next(),)*
-                    }
-                }
+pub struct Off;
 
-                fn size_hint(&self) -> (usize, Option<usize>) {
-                    match self {
-                        $(ModeIter::$n(i) => i.size_hint(),)*
-                    }
-                }
+impl Pattern for Off {
+    fn render(&self, _: &FrameContext, _: &mut ModeState, frame: &mut Frame) {
+        frame.fill(Rgb::BLACK);
+    }
+}
+
+pub struct Neutral;
+
+impl Pattern for Neutral {
+    fn render(&self, _: &FrameContext, _: &mut ModeState, frame: &mut Frame) {
+        // On RGBW strips, this is shown with the white LEDs.
+        frame.fill(NEUTRAL_WHITE);
+    }
+}
+
+pub struct Fireplace;
+
+impl Pattern for Fireplace {
+    fn render(&self, ctx: &FrameContext, _: &mut ModeState, frame: &mut Frame) {
+        let params = ctx.settings.fireplace;
+        seemingly_random_vibration(
+            ctx.clock_value,
+            params.intensity_range.0,
+            params.intensity_range.1,
+            frame,
+            |intensity| {
+                let intensity = 255 - ONE_MINUS_EXP_MINUS_X_TABLE.load((255 - intensity) as usize);
+                rotate_hue(
+                    params.dim_color.lerp(params.bright_color, intensity),
+                    params.hue_shift,
+                )
+            },
+        );
+    }
+}
+
+pub struct WholeStripAlternatingColor;
+
+impl Pattern for WholeStripAlternatingColor {
+    fn render(&self, ctx: &FrameContext, _: &mut ModeState, frame: &mut Frame) {
+        let params = &ctx.settings.alternating_color;
+        let num_colors = u32::from(params.num_colors);
+        let millis = clock::millis_in_period(ctx.clock_value, num_colors * 2000);
+        let entry = millis / 2000;
+        let color1 = params.palette.0[entry as usize];
+        let color2 = params.palette.0[((entry + 1) % num_colors) as usize];
+        frame.fill(color1.lerp(color2, (millis % 2000 * 256 / 2000) as u8));
+    }
+}
+
+pub struct SegmentLights;
+
+impl Pattern for SegmentLights {
+    fn render(&self, ctx: &FrameContext, _: &mut ModeState, frame: &mut Frame) {
+        let segment_offset = {
+            let base = clock::millis_in_period(ctx.clock_value, 35 * 256) / 35;
+            if base > 128 {
+                256 - base
+            } else {
+                base
             }
         };
+
+        let params = &ctx.settings.segment_lights;
+        let segment_width = u32::from(params.segment_width);
+        for (led_pos, color) in frame.iter_mut().enumerate() {
+            let segment_num = (led_pos as u32 + segment_offset) / segment_width;
+            *color = params.palette.0[(segment_num % u32::from(params.num_colors)) as usize];
+        }
     }
+}
 
-    gen!(
-        OffNw,
-        OffSe,
-        Fireplace,
-        Neutral,
-        SegmentLights,
-        WholeStripAlternatingColor
-    );
-
-    if matches!(mode, Mode::PartyCycle) {
-        mode = match ((clock_value.as_millis() as u16) / 4780) % 2 {
+/// Alternates between other modes.
+pub struct PartyCycle;
+
+impl Pattern for PartyCycle {
+    fn render(&self, ctx: &FrameContext, state: &mut ModeState, frame: &mut Frame) {
+        let mode = match clock::millis_in_period(ctx.clock_value, 2 * 4780) / 4780 {
             0 => Mode::WholeStripAlternatingColor,
             1 => Mode::SegmentLights,
             _ => unreachable!(),
-        }
+        };
+        (mode.info().render)(ctx, state, frame);
     }
+}
 
-    match (mode, strip) {
-        (Mode::Off, Strip::NorthWest) => {
-            ModeIter::OffNw(iter::repeat([0, 0, 0]).take(NORTH_LEDS + WEST_LEDS))
-        }
-        (Mode::Off, Strip::SouthEast) => {
-            ModeIter::OffSe(iter::repeat([0, 0, 0]).take(SOUTH_LEDS + EAST_LEDS))
-        }
-        (Mode::Neutral, _) => ModeIter::Neutral(
-            iter::repeat({
-                // Roughly white.
-                [140, 50, 20]
-            })
-            .take(match strip {
-                Strip::NorthWest => NORTH_LEDS + WEST_LEDS,
-                Strip::SouthEast => SOUTH_LEDS + EAST_LEDS,
-            }),
-        ),
-        (Mode::PartyCycle, _) => unreachable!(), // Handled above.
-        (Mode::Fireplace, strip) => ModeIter::Fireplace(seemingly_random_vibration(
-            clock_value,
-            strip,
-            0,
-            255,
-            iter::repeat([0, 0, 0]).take(match strip {
-                Strip::NorthWest => NORTH_LEDS + WEST_LEDS,
-                Strip::SouthEast => SOUTH_LEDS + EAST_LEDS,
-            }),
-            |_, intensity| {
-                let color1 = [9, 3, 0];
-                let color2 = [40, 5, 0];
-                let intensity = 255 - ONE_MINUS_EXP_MINUS_X_TABLE[(255 - intensity) as usize];
-                [
-                    ((color1[0] as u16 * (255 - intensity) as u16
-                        + color2[0] as u16 * intensity as u16)
-                        / 255) as u8,
-                    ((color1[1] as u16 * (255 - intensity) as u16
-                        + color2[1] as u16 * intensity as u16)
-                        / 255) as u8,
-                    ((color1[2] as u16 * (255 - intensity) as u16
-                        + color2[2] as u16 * intensity as u16)
-                        / 255) as u8,
-                ]
-            },
-        )),
-        (Mode::WholeStripAlternatingColor, strip) => {
-            let color = |v| -> [u8; 3] {
-                match v % 6 {
-                    0 => [128, 0, 0],
-                    1 => [80, 0, 32],
-                    2 => [0, 0, 128],
-                    3 => [20, 128, 50],
-                    4 => [0, 128, 0],
-                    5 => [64, 64, 0],
+pub struct Music;
+
+impl Pattern for Music {
+    fn render(&self, ctx: &FrameContext, _: &mut ModeState, frame: &mut Frame) {
+        let audio = ctx.audio;
+
+        // The loudness is shown as a VU meter going around the room, going from green to
+        // red. The rest of the room shows segments whose brightness is the level of the bass,
+        // mid and treble. Beats make the whole room flash.
+        let vu_meter_end = u32::from(audio.level) * TOTAL_LEDS as u32 / 255;
+        for (led_pos, out) in frame.iter_mut().enumerate() {
+            let led_pos = led_pos as u32;
+
+            let color = if led_pos < vu_meter_end {
+                // The spectrum conversion goes straight from green to red, without the brighter
+                // yellow of the rainbow conversion.
+                let hue = 85 - (led_pos * 85 / TOTAL_LEDS as u32) as u8;
+                Hsv::new(hue, 255, 128).to_rgb_spectrum()
+            } else {
+                match (led_pos / 6) % 3 {
+                    0 => Rgb::new(audio.bass / 2, 0, audio.bass / 8),
+                    1 => Rgb::new(0, audio.mid / 2, audio.mid / 8),
+                    2 => Rgb::new(0, audio.treble / 8, audio.treble / 2),
                     _ => unreachable!(),
                 }
             };
 
-            let color1 = color(clock_value.as_secs() as u8 / 2);
-            let color2 = color((clock_value.as_secs() as u8 / 2).wrapping_add(1));
-            let lerp = ((clock_value.as_millis() as u32 * 256) / 2000) as u8;
+            *out =
+                color.saturating_add(Rgb::new(audio.flash / 4, audio.flash / 8, audio.flash / 4));
+        }
+    }
+}
 
-            // TODO: dry
-            fn avg(a: u8, b: u8, b_weight: u8) -> u8 {
-                let res =
-                    u16::from(a) * u16::from(255 - b_weight) + u16::from(b) * u16::from(b_weight);
-                u8::try_from(res / 255).unwrap()
-            }
+pub struct Rainbow;
 
-            let final_color = [
-                avg(color1[0], color2[0], lerp),
-                avg(color1[1], color2[1], lerp),
-                avg(color1[2], color2[2], lerp),
-            ];
+impl Pattern for Rainbow {
+    fn render(&self, ctx: &FrameContext, _: &mut ModeState, frame: &mut Frame) {
+        rainbow(ctx.clock_value, ctx.settings.rainbow.saturation, frame);
+    }
+}
 
-            ModeIter::WholeStripAlternatingColor(iter::repeat(final_color).take(match strip {
-                Strip::NorthWest => NORTH_LEDS + WEST_LEDS,
-                Strip::SouthEast => SOUTH_LEDS + EAST_LEDS,
-            }))
-        }
-        (Mode::SegmentLights, strip) => {
-            let segment_offset = {
-                let base = (clock_value.as_millis() as u32) / 35;
-                let base = base % 256;
-                if base > 128 {
-                    256 - base
-                } else {
-                    base
-                }
-            };
+pub struct RainbowGlitter;
 
-            let iter = (0u32..).map(move |idx| {
-                let led_pos = if matches!(strip, Strip::NorthWest) {
-                    idx
-                } else {
-                    ((SOUTH_LEDS + EAST_LEDS) as u32 - idx) + (NORTH_LEDS + WEST_LEDS) as u32
-                };
-
-                let segment_num = (led_pos + segment_offset) / 6;
-                match segment_num % 7 {
-                    0 => [128, 0, 0],
-                    1 => [100, 50, 50],
-                    2 => [0, 64, 64],
-                    3 => [64, 64, 0],
-                    4 => [0, 0, 128],
-                    5 => [0, 128, 0],
-                    6 => [64, 0, 64],
-                    _ => unreachable!(),
-                }
-            });
+impl Pattern for RainbowGlitter {
+    fn render(&self, ctx: &FrameContext, _: &mut ModeState, frame: &mut Frame) {
+        rainbow(
+            ctx.clock_value,
+            ctx.settings.rainbow_glitter.saturation,
+            frame,
+        );
 
-            ModeIter::SegmentLights(iter.take(match strip {
-                Strip::NorthWest => NORTH_LEDS + WEST_LEDS,
-                Strip::SouthEast => SOUTH_LEDS + EAST_LEDS,
-            }))
+        // Every `SPARKLE_PERIOD_MS`, a sparkle appears at a seemingly random position. Its
+        // brightness follows half a period of a sine wave during `SPARKLE_NUM_PERIODS` periods.
+        const SPARKLE_PERIOD_MS: u32 = 40;
+        const SPARKLE_NUM_PERIODS: u32 = 8;
+
+        // Sparkles are numbered modulo `NUM_SPARKLE_IDS`, which is large enough for the same
+        // positions not to visibly repeat.
+        const NUM_SPARKLE_IDS: u32 = 1 << 16;
+
+        let time = clock::millis_in_period(ctx.clock_value, SPARKLE_PERIOD_MS * NUM_SPARKLE_IDS);
+        let current_sparkle = time / SPARKLE_PERIOD_MS;
+        for num_periods_ago in 0..SPARKLE_NUM_PERIODS {
+            let sparkle = (current_sparkle + NUM_SPARKLE_IDS - num_periods_ago) % NUM_SPARKLE_IDS;
+            let age = time % SPARKLE_PERIOD_MS + num_periods_ago * SPARKLE_PERIOD_MS;
+            let angle = age * 128 / (SPARKLE_PERIOD_MS * SPARKLE_NUM_PERIODS);
+            let brightness = (SIN_TABLE.load(angle as usize) as u8).saturating_mul(4);
+
+            // Multiplying by a large odd constant scatters consecutive sparkles around the room.
+            let led_pos = (sparkle.wrapping_mul(2654435761) >> 16) as usize % TOTAL_LEDS;
+            frame[led_pos] = frame[led_pos].saturating_add(Rgb::WHITE.scale(brightness));
         }
     }
 }
 
-fn west_to_east_gradiant_modifier(
-    strip: Strip,
-    iter: impl Iterator<Item = [u8; 3]> + Clone,
-) -> impl Iterator<Item = [u8; 3]> + Clone {
-    let mut n = 0;
-    iter.map(move |item| {
-        const MIN_INTENSITY: u16 = 4;
-
-        let intensity = if matches!(strip, Strip::NorthWest) {
-            if n < WEST_LEDS {
-                MIN_INTENSITY
-            } else {
-                MIN_INTENSITY
-                    + (256 - MIN_INTENSITY) * u16::try_from(n - WEST_LEDS).unwrap()
-                        / u16::try_from(NORTH_LEDS).unwrap()
-            }
-        } else {
-            if n < SOUTH_LEDS {
-                MIN_INTENSITY
-                    + (256 - MIN_INTENSITY) * u16::try_from(n).unwrap()
-                        / u16::try_from(SOUTH_LEDS).unwrap()
+pub struct TheatreChase;
+
+impl Pattern for TheatreChase {
+    fn render(&self, ctx: &FrameContext, _: &mut ModeState, frame: &mut Frame) {
+        let params = ctx.settings.theatre_chase;
+        let step = clock::millis_in_period(ctx.clock_value, 3 * 100) / 100;
+        let hue_add = clock::millis_in_period(ctx.clock_value, 256 * 40) / 40;
+
+        for (led_pos, color) in frame.iter_mut().enumerate() {
+            let led_pos = led_pos as u32;
+            *color = if (led_pos + step) % 3 == 0 {
+                let hue = (hue_add + led_pos * 256 / TOTAL_LEDS as u32) as u8;
+                Hsv::new(hue, params.saturation, 255).into()
             } else {
-                256
-            }
-        };
+                Rgb::BLACK
+            };
+        }
+    }
+}
 
-        n += 1;
+pub struct HueCycle;
 
-        [
-            (intensity * u16::from(item[0]) / 256) as u8,
-            (intensity * u16::from(item[1]) / 256) as u8,
-            (intensity * u16::from(item[2]) / 256) as u8,
-        ]
-    })
+impl Pattern for HueCycle {
+    fn render(&self, ctx: &FrameContext, _: &mut ModeState, frame: &mut Frame) {
+        // A full turn takes a minute.
+        let hue = (clock::millis_in_period(ctx.clock_value, 60000) * 256 / 60000) as u8;
+        frame.fill(Hsv::new(hue, ctx.settings.hue_cycle.saturation, 255).into());
+    }
 }
 
-fn flashing(
-    updates_wrapping_counter: u8,
-    iter: impl Iterator<Item = [u8; 3]> + Clone,
-) -> impl Iterator<Item = [u8; 3]> + Clone {
-    let flash = updates_wrapping_counter % 2 == 0;
-    iter.map(move |color| {
-        if flash {
-            [
-                cmp::max(cmp::min(u16::from(color[0]) * 9 / 4, 255) as u8, 1),
-                cmp::max(cmp::min(u16::from(color[1]) * 9 / 4, 255) as u8, 1),
-                cmp::max(cmp::min(u16::from(color[2]) * 9 / 4, 255) as u8, 1),
-            ]
-        } else {
-            color
+/// Writes to `frame` a rainbow going once around the room, and making a full turn every
+/// 5 seconds.
+fn rainbow(clock_value: Duration, saturation: u8, frame: &mut Frame) {
+    let hue_add = (clock::millis_in_period(clock_value, 5000) * 256 / 5000) as u8;
+
+    for (led_pos, color) in frame.iter_mut().enumerate() {
+        let hue = hue_add.wrapping_add((led_pos * 256 / TOTAL_LEDS) as u8);
+        *color = Hsv::new(hue, saturation, 255).into();
+    }
+}
+
+pub struct Meteor;
+
+impl Pattern for Meteor {
+    fn render(&self, ctx: &FrameContext, _: &mut ModeState, frame: &mut Frame) {
+        // Positions are expressed in 1/256th of LED, so that the comets move smoothly.
+        const LOOP_LEN: u32 = TOTAL_LEDS as u32 * 256;
+        // A comet goes around the room in 8 seconds.
+        const LOOP_DURATION_MS: u32 = 8000;
+
+        let params = ctx.settings.meteor;
+        let first_head = clock::millis_in_period(ctx.clock_value, LOOP_DURATION_MS) * LOOP_LEN
+            / LOOP_DURATION_MS;
+        let trail_len = cmp::max(u32::from(params.trail_length), 1) * 256;
+
+        for (led_pos, color) in frame.iter_mut().enumerate() {
+            let led_pos = led_pos as u32 * 256;
+
+            let mut brightness = 0;
+            for comet in 0..u32::from(params.count) {
+                let head = (first_head + comet * LOOP_LEN / u32::from(params.count)) % LOOP_LEN;
+                // Distance behind the head, wrapping around at the end of the room.
+                let distance = (head + LOOP_LEN - led_pos) % LOOP_LEN;
+                if distance < trail_len {
+                    let index = (distance * 255 / trail_len) as usize;
+                    brightness =
+                        cmp::max(brightness, 255 - ONE_MINUS_EXP_MINUS_X_TABLE.load(index));
+                }
+            }
+
+            *color = params.color.scale(brightness);
         }
-    })
+    }
 }
 
-// TODO: has a weird API now
 fn seemingly_random_vibration(
     clock_value: Duration,
-    strip: Strip,
     wave_min_intensity: u8,
     wave_max_intensity: u8,
-    iter: impl Iterator<Item = [u8; 3]> + Clone,
-    mut map: impl FnMut([u8; 3], u8) -> [u8; 3] + Clone,
-) -> impl Iterator<Item = [u8; 3]> + Clone {
-    let wave1_add = ((clock_value.as_millis() as u32) / 6) as u32;
-    let wave2_add = ((clock_value.as_millis() as u32) / 30) as u32;
-    let wave3_add = ((clock_value.as_millis() as u32) / 21) as u32;
-    let wave4_add = ((clock_value.as_millis() as u32) / 22) as u32;
-
-    iter.enumerate().map(move |(idx, val)| {
-        let led_pos = if matches!(strip, Strip::NorthWest) {
-            idx as u32
-        } else {
-            ((SOUTH_LEDS + EAST_LEDS) as u32 - idx as u32) + (NORTH_LEDS + WEST_LEDS) as u32
-        };
-
-        let angle1 = u32::from(wave1_add) + 5 * 256 * led_pos / TOTAL_LEDS as u32;
-        let sin_value1 = i16::from(SIN_TABLE[(angle1 & 0xff) as usize]);
-        let angle2 = 3 * 256 * led_pos / TOTAL_LEDS as u32 - u32::from(wave2_add);
-        let sin_value2 = i16::from(SIN_TABLE[(angle2 & 0xff) as usize]);
-        let angle3 = u32::from(wave3_add) + 7 * 256 * led_pos / TOTAL_LEDS as u32;
-        let sin_value3 = i16::from(SIN_TABLE[(angle3 & 0xff) as usize]);
-        let angle4 = 11 * 256 * led_pos / TOTAL_LEDS as u32 - u32::from(wave4_add);
-        let sin_value4 = i16::from(SIN_TABLE[(angle4 & 0xff) as usize]);
-
-        let sin_value = cmp::min(
-            127,
-            cmp::max(
-                -128,
-                (sin_value1 + sin_value2 + sin_value3 + sin_value4) / 2,
-            ),
-        );
+    frame: &mut Frame,
+    mut map: impl FnMut(u8) -> Rgb,
+) {
+    // Only the angles modulo 256 matter.
+    let wave1_add = clock::millis_in_period(clock_value, 6 * 256) / 6;
+    let wave2_add = clock::millis_in_period(clock_value, 30 * 256) / 30;
+    let wave3_add = clock::millis_in_period(clock_value, 21 * 256) / 21;
+    let wave4_add = clock::millis_in_period(clock_value, 22 * 256) / 22;
+
+    for (led_pos, out) in frame.iter_mut().enumerate() {
+        let led_pos = led_pos as u32;
+
+        let angle1 = wave1_add + 5 * 256 * led_pos / TOTAL_LEDS as u32;
+        let sin_value1 = i16::from(SIN_TABLE.load((angle1 & 0xff) as usize));
+        let angle2 = (3 * 256 * led_pos / TOTAL_LEDS as u32).wrapping_sub(wave2_add);
+        let sin_value2 = i16::from(SIN_TABLE.load((angle2 & 0xff) as usize));
+        let angle3 = wave3_add + 7 * 256 * led_pos / TOTAL_LEDS as u32;
+        let sin_value3 = i16::from(SIN_TABLE.load((angle3 & 0xff) as usize));
+        let angle4 = (11 * 256 * led_pos / TOTAL_LEDS as u32).wrapping_sub(wave4_add);
+        let sin_value4 = i16::from(SIN_TABLE.load((angle4 & 0xff) as usize));
+
+        let sin_value = ((sin_value1 + sin_value2 + sin_value3 + sin_value4) / 2).clamp(-128, 127);
 
         let intensity = ((sin_value + 128) as u16
             * (wave_max_intensity as u16 - wave_min_intensity as u16)
             / 255
             + wave_min_intensity as u16) as u8;
 
-        map(val, intensity)
-    })
-}
-
-fn slowly_changing_color(clock_value: Duration) -> [u8; 3] {
-    const COLOR_DURATION: u32 = 60000;
-
-    let colors = [
-        [255, 0, 0],
-        [0, 128, 128],
-        [0, 0, 255],
-        [128, 128, 0],
-        [0, 255, 0],
-        [128, 0, 128],
-    ];
-
-    let step = (clock_value.as_millis() as u32) % (COLOR_DURATION * colors.len() as u32);
-
-    let color_from_idx = (step / COLOR_DURATION) as usize;
-    let color_from = colors[color_from_idx];
-    let color_to_idx = (color_from_idx + 1) % colors.len();
-    let color_to = colors[color_to_idx];
-
-    assert!(COLOR_DURATION < u16::max_value() as u32); // Overflow check.
-    let progress = (((step % COLOR_DURATION) << 16) / COLOR_DURATION) as u32;
-    let one_minus_progress = u16::max_value() as u32 - progress;
-
-    [
-        ((color_from[0] as u32 * one_minus_progress + color_to[0] as u32 * progress) >> 16) as u8,
-        ((color_from[1] as u32 * one_minus_progress + color_to[1] as u32 * progress) >> 16) as u8,
-        ((color_from[2] as u32 * one_minus_progress + color_to[2] as u32 * progress) >> 16) as u8,
-    ]
-}
-
-fn colors_rotation_by_side(
-    clock_value: Duration,
-    strip: Strip,
-) -> impl Iterator<Item = [u8; 3]> + Clone {
-    let side_add = (((clock_value.as_secs() as u32) / 2) & 0xff) as u16;
-
-    (0..).map_while(move |counter| {
-        let side_num = match (counter, strip) {
-            (n, Strip::NorthWest) if n >= NORTH_LEDS + WEST_LEDS => return None,
-            (n, Strip::NorthWest) if n >= WEST_LEDS => 1,
-            (_, Strip::NorthWest) => 0,
-            (n, Strip::SouthEast) if n >= SOUTH_LEDS + EAST_LEDS => return None,
-            (n, Strip::SouthEast) if n >= SOUTH_LEDS => 2,
-            (_, Strip::SouthEast) => 3,
-        };
-
-        let side_num_adjusted = (side_num + side_add) % 4;
-
-        Some(match side_num_adjusted {
-            0 => [255, 0, 0],
-            1 => [128, 0, 128],
-            2 => [0, 0, 255],
-            3 => [0, 255, 0],
-            _ => unreachable!(),
-        })
-    })
-}
-
-fn wave_modifier_nw(
-    num_periods: u16,
-    angle_add: u8,
-    iter: impl Iterator<Item = [u8; 3]> + Clone,
-) -> impl Iterator<Item = [u8; 3]> + Clone {
-    iter.enumerate().map(move |(idx, val)| {
-        let led_pos = idx as u32;
-        let angle =
-            u32::from(angle_add) + u32::from(num_periods) * 256 * led_pos / TOTAL_LEDS as u32;
-        let sin_value = i16::from(SIN_TABLE[(angle & 0xff) as usize]);
-        let map = move |n| (i16::from(n) * (sin_value + 64) / 128) as u8;
-        [map(val[0]), map(val[1]), map(val[2])]
-    })
-}
-
-fn wave_modifier_se(
-    num_periods: u16,
-    angle_add: u8,
-    iter: impl Iterator<Item = [u8; 3]> + Clone,
-) -> impl Iterator<Item = [u8; 3]> + Clone {
-    iter.enumerate().map(move |(idx, val)| {
-        let led_pos =
-            ((SOUTH_LEDS + EAST_LEDS) as u32 - idx as u32) + (NORTH_LEDS + WEST_LEDS) as u32;
-        let angle =
-            u32::from(angle_add) + u32::from(num_periods) * 256 * led_pos / TOTAL_LEDS as u32;
-        let sin_value = i16::from(SIN_TABLE[(angle & 0xff) as usize]);
-        let map = move |n| (i16::from(n) * (sin_value + 64) / 128) as u8;
-        [map(val[0]), map(val[1]), map(val[2])]
-    })
-}
-
-fn cursor_add_nw(
-    clock_value: Duration,
-    cursor_color: [u8; 3],
-    iter: impl Iterator<Item = [u8; 3]> + Clone,
-) -> impl Iterator<Item = [u8; 3]> + Clone {
-    let cursor_pos = (((clock_value.as_millis() / 500) as u32)
-        % u32::try_from(WEST_LEDS + NORTH_LEDS + EAST_LEDS + SOUTH_LEDS).unwrap())
-        as u16;
-    iter.enumerate().map(move |(pos, value)| {
-        if pos == usize::from(cursor_pos) {
-            cursor_color
-        } else {
-            value
-        }
-    })
+        *out = map(intensity);
+    }
 }
 
-fn cursor_add_se(
-    clock_value: Duration,
-    cursor_color: [u8; 3],
-    iter: impl Iterator<Item = [u8; 3]> + Clone,
-) -> impl Iterator<Item = [u8; 3]> + Clone {
-    let cursor_pos = (((clock_value.as_millis() / 500) as u32)
-        % u32::try_from(WEST_LEDS + NORTH_LEDS + EAST_LEDS + SOUTH_LEDS).unwrap())
-        as u16;
-    let cursor_pos_adj = cursor_pos
-        .checked_sub((WEST_LEDS + NORTH_LEDS) as u16)
-        .map(|n| (SOUTH_LEDS + EAST_LEDS) as u16 - n)
-        .unwrap_or(u16::max_value());
-    iter.enumerate().map(move |(pos, value)| {
-        if pos == usize::from(cursor_pos_adj) {
-            cursor_color
-        } else {
-            value
-        }
-    })
-}
+/// Rotates the hue of the given color. A `hue_shift` of 256 would represent a full turn.
+fn rotate_hue(color: Rgb, hue_shift: u8) -> Rgb {
+    // Rotating the hue by a third of a turn is the same as permuting the channels. In-between,
+    // we interpolate between the two nearest permutations.
+    let rotate_third = |c: Rgb| Rgb::new(c.b(), c.r(), c.g());
 
-fn onoff_periodic(
-    clock_value: Duration,
-    iter: impl Iterator<Item = [u8; 3]> + Clone,
-) -> impl Iterator<Item = [u8; 3]> + Clone {
-    let is_on = (clock_value.as_secs() % 6) < 3;
-    iter.map(move |c| if is_on { c } else { [0, 0, 0] })
+    let position = u16::from(hue_shift) * 3;
+    let mut from = color;
+    for _ in 0..(position >> 8) {
+        from = rotate_third(from);
+    }
+    let to = rotate_third(from);
+    from.lerp(to, (position & 0xff) as u8)
 }
 
 include!(concat!(env!("OUT_DIR"), "/exp_table.rs"));
@@ -486,9 +424,11 @@ const fn sin_approx(angle: u8) -> i8 {
 
 macro_rules! gen_sin_table {
     ($($n:expr),*) => {
-        const SIN_TABLE: [i8; 256] = [
-            $(sin_approx($n)),*
-        ];
+        progmem! {
+            pub static SIN_TABLE: [i8; 256] = [
+                $(sin_approx($n)),*
+            ];
+        }
     };
 }
 
@@ -508,3 +448,17 @@ gen_sin_table!(
     231, 232, 233, 234, 235, 236, 237, 238, 239, 240, 241, 242, 243, 244, 245, 246, 247, 248, 249,
     250, 251, 252, 253, 254, 255
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips() {
+        // The frame is split between the strips.
+        assert_eq!(
+            Strip::NorthWest.num_leds() + Strip::SouthEast.num_leds(),
+            TOTAL_LEDS
+        );
+    }
+}
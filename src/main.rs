@@ -1,21 +1,121 @@
-#![feature(
-    asm_experimental_arch,
-    asm_const,
-    abi_avr_interrupt,
-    default_alloc_error_handler
+#![cfg_attr(
+    target_arch = "avr",
+    feature(
+        asm_experimental_arch,
+        asm_const,
+        abi_avr_interrupt,
+        default_alloc_error_handler
+    )
 )]
-#![no_std]
-#![no_main]
+#![cfg_attr(target_arch = "avr", no_std)]
+#![cfg_attr(target_arch = "avr", no_main)]
 
-use core::time::Duration;
+#[cfg(target_arch = "avr")]
+use core::{cmp, mem::MaybeUninit, ptr, time::Duration};
 
+// Outside of the AVR, only the tests are built, on the host, so the modules are left out of the
+// empty `main` below.
+#[cfg(any(target_arch = "avr", test))]
+mod analog;
+#[cfg(any(target_arch = "avr", test))]
+mod audio;
+#[cfg(any(target_arch = "avr", test))]
+mod clock;
+#[cfg(any(target_arch = "avr", test))]
+mod color;
+#[cfg(any(target_arch = "avr", test))]
+mod fire;
+#[cfg(any(target_arch = "avr", test))]
+mod frame_rate;
+#[cfg(target_arch = "avr")]
 mod hal;
+#[cfg(any(target_arch = "avr", test))]
+mod ir;
+#[cfg(any(target_arch = "avr", test))]
+mod layers;
+#[cfg(any(target_arch = "avr", test))]
 mod leds;
+#[cfg(any(target_arch = "avr", test))]
+mod output;
+#[cfg(any(target_arch = "avr", test))]
+mod palette;
+#[cfg(any(target_arch = "avr", test))]
+mod params;
+#[cfg(any(target_arch = "avr", test))]
+mod pattern;
+#[cfg(any(target_arch = "avr", test))]
+mod progmem;
+#[cfg(any(target_arch = "avr", test))]
+mod random;
+#[cfg(any(target_arch = "avr", test))]
+mod serial;
+#[cfg(any(target_arch = "avr", test))]
+mod settings;
+#[cfg(any(target_arch = "avr", test))]
+mod sleep;
+#[cfg(any(target_arch = "avr", test))]
+mod sunrise;
+#[cfg(any(target_arch = "avr", test))]
+mod time_of_day;
+#[cfg(any(target_arch = "avr", test))]
+mod twinkle;
+#[cfg(any(target_arch = "avr", test))]
+mod watchdog;
 
+/// `true` if a strip is connected to the hardware SPI.
+#[cfg(target_arch = "avr")]
+const USES_SPI: bool = matches!(leds::Strip::NorthWest.led_type(), output::LedType::Apa102)
+    || matches!(leds::Strip::SouthEast.led_type(), output::LedType::Apa102);
+#[cfg(any(target_arch = "avr", test))]
+const _: () = assert!(
+    !(matches!(leds::Strip::NorthWest.led_type(), output::LedType::Apa102)
+        && matches!(leds::Strip::SouthEast.led_type(), output::LedType::Apa102)),
+    "only one strip can be connected to the hardware SPI"
+);
+
+#[cfg(target_arch = "avr")]
 static mut NUM_TIMER0_OVERFLOWS: u32 = 0;
 
+/// Decoder of the infrared remote control. Fed by the `INT0` interrupt handler.
+#[cfg(target_arch = "avr")]
+static mut IR_DECODER: ir::NecDecoder = ir::NecDecoder::new();
+/// Last event produced by [`IR_DECODER`] and not processed yet by the main loop.
+#[cfg(target_arch = "avr")]
+static mut IR_EVENT: Option<ir::Event> = None;
+
+/// Bytes received through the serial port. Fed by the `USART_RX` interrupt handler.
+#[cfg(target_arch = "avr")]
+static mut SERIAL_RX: serial::RxQueue = serial::RxQueue::new();
+
+/// Mode currently being displayed, for the `WDT` interrupt handler.
+#[cfg(target_arch = "avr")]
+static mut CURRENT_MODE: leds::Mode = leds::Mode::Off;
+/// Written by the `WDT` interrupt handler when the main loop is stuck. Not initialized at
+/// startup, so that it survives the reset by the watchdog.
+#[cfg(target_arch = "avr")]
+#[link_section = ".noinit"]
+static mut CRASH_RECORD: MaybeUninit<watchdog::CrashRecord> = MaybeUninit::uninit();
+
+// Only built to run the tests on the host.
+#[cfg(not(target_arch = "avr"))]
+fn main() {}
+
+#[cfg(target_arch = "avr")]
 #[no_mangle]
 pub extern "C" fn main() {
+    // After a reset by the watchdog, the watchdog stays enabled with a timeout of 16ms, so it
+    // must be configured before anything else.
+    let reset_cause = watchdog::ResetCause::new(hal::take_reset_flags(), || unsafe {
+        // The record has been written before the reset, unless the firmware has just been
+        // uploaded, in which case the magic number tells that it isn't valid. It is read as
+        // volatile memory rather than as an initialized value, which the compiler can't assume.
+        ptr::read_volatile(CRASH_RECORD.as_ptr())
+    });
+    unsafe {
+        CRASH_RECORD.write(watchdog::CrashRecord::NONE);
+    }
+    hal::enable_watchdog();
+
     // Enable interrupts.
     // They have to be enabled at some point for things to work, and there's no reason to not do
     // it right at the beginning.
@@ -27,8 +127,33 @@ pub extern "C" fn main() {
     // On the Arduino Uno, they are the ones marked "8" (B0) and "10" (B2) on DIGITAL side.
     hal::enable_bport_out::<0>();
     hal::enable_bport_out::<2>();
-    // Set port B4 as input port. It is marked "12" on DIGITAL side.
+    // APA102 strips are connected to the hardware SPI, with the data on port B3 and the clock on
+    // port B5. They are marked "11" (B3) and "13" (B5) on DIGITAL side.
+    if USES_SPI {
+        hal::enable_bport_out::<3>();
+        hal::enable_bport_out::<5>();
+        hal::enable_spi();
+    }
+    // Set port B4 as input port. It is marked "12" on DIGITAL side, and is connected to the push
+    // button.
     hal::enable_bport_in::<4>();
+    // Set port D2 as input port. It is marked "2" on DIGITAL side, and is connected to the output
+    // of the infrared receiver. Any change of its value triggers the `INT0` interrupt.
+    hal::enable_dport_in::<2>();
+    hal::enable_int0_any_change();
+    // Potentiometers are connected to the analog inputs A0 to A3.
+    hal::enable_adc();
+    // Commands can be sent through the serial port, using the USB connection of the Arduino.
+    hal::enable_uart(serial::UBRR);
+
+    if let watchdog::ResetCause::Crash(crash, crashed_mode) = reset_cause {
+        write_serial(crash.message());
+        if let Some(crashed_mode) = crashed_mode {
+            write_serial(" in mode ");
+            write_serial(crashed_mode.info().name.as_str());
+        }
+        write_serial(", safe mode\n");
+    }
 
     // Enable the timer0 with a prescaler of 64.
     // This means that every 64 cycles the clock timer increases by 1. After 16384 cycles
@@ -54,132 +179,626 @@ pub extern "C" fn main() {
         );
     }
 
+    // Extends the counter of overflows of timer0, so that the clock never wraps around.
+    let mut clock = clock::Clock::new();
+
+    // Makes the frames last the same time, whatever the mode.
+    let mut frame_limiter = frame_rate::FrameLimiter::new();
+
     // If `Some`, the push button has been pressed since the given clock value.
     let mut button_is_pressed_since = None::<Duration>;
 
-    // Mode currently being displayed.
-    let mut mode = leds::Mode::Off;
+    // Clock value of the last press of the push button, and mode that was displayed before it.
+    let mut last_button_press = None::<(Duration, leds::Mode)>;
+
+    // If `Some`, the LEDs fade out then switch off when the timer expires.
+    let mut sleep_timer = None::<sleep::SleepTimer>;
+
+    // Mode currently being displayed. After a crash, a safe mode makes sure that the room isn't
+    // left dark.
+    let mut mode = match reset_cause {
+        watchdog::ResetCause::Normal => leds::Mode::Off,
+        watchdog::ResetCause::Crash(..) => watchdog::SAFE_MODE,
+    };
 
     let mut updates_wrapping_counter: u8 = 0;
 
-    // Buffer to collect the LED data in. Must be large enough to fit all the data of all the LED
-    // strips at once, otherwise the sending timing will not work.
-    let mut data_buffer = [0; leds::TOTAL_LEDS * 3];
+    // Parameters of the effects, controlled with the infrared remote and the potentiometers.
+    let mut settings = settings::DEFAULT.read();
+
+    let mut analog_inputs = [analog::AnalogInput::new(); analog::NUM_INPUTS];
+
+    // Analysis of the sound of the microphone, used by the music mode.
+    let mut audio_analyzer = audio::AudioAnalyzer::new();
+
+    // Clock used to animate the effects. Runs faster or slower than the real clock depending on
+    // the speed of the current mode. Because it is accumulated rather than computed by
+    // multiplying the real clock, changing the speed doesn't make the effects jump.
+    let mut effect_clock = Duration::ZERO;
+    let mut previous_clock_value = Duration::ZERO;
+
+    // Last action performed by the infrared remote, repeated if the key is held down.
+    let mut last_ir_action = None::<ir::Action>;
+
+    let mut serial_line_reader = serial::LineReader::new();
+
+    // Converts the clock into the time of the day, once the time has been sent through the serial
+    // port.
+    let mut wall_clock = None::<time_of_day::WallClock>;
+
+    // `true` if the mode is off and black has been sent to the LEDs since, so that there is no
+    // need to send anything else until the mode changes.
+    let mut leds_are_dark = false;
+
+    // `true` if the sunrise of the alarm was in progress during the previous frame.
+    let mut was_sunrise = false;
+
+    // Colors of all the LEDs, in layout order. The data of the strips is encoded in place where
+    // possible, but the frame is restored once sent, so that patterns can build on it.
+    let mut frame: pattern::Frame = [color::Rgb::BLACK; leds::TOTAL_LEDS];
+
+    // State of the current mode, for the modes that need one.
+    let mut mode_state = pattern::ModeState::None;
+
+    // The least significant bits of the samples of the microphone are mostly noise, which makes
+    // them a good seed.
+    let rng = random::Rng::new(random::Rng::seed_from_noise(
+        (0..32).map(|_| hal::read_adc(audio::MIC_CHANNEL)),
+    ));
 
     loop {
-        // TODO: set NUM_TIMER0_OVERFLOWS to 0 while the mode is off, so that we don't ever see the clock overflow
-
-        let clock_value = unsafe {
-            // In order to grab the clock value without running the risk of a race condition, we
-            // need to interrupts. For this reason, this is done directly in assembly code.
-
-            let sreg: u8;
-            let subtimer: u8;
-            let num_timer0_overflows_byte0: u8;
-            let num_timer0_overflows_byte1: u8;
-            let num_timer0_overflows_byte2: u8;
-            let num_timer0_overflows_byte3: u8;
-
-            core::arch::asm!(r#"
-                lds {sreg}, 0x5f  // SREG
-                cli
-                lds {subtimer}, 0x46
-                ld {byte0}, X+
-                ld {byte1}, X+
-                ld {byte2}, X+
-                ld {byte3}, X+
-                sts 0x5f, {sreg}
-                "#,
-                sreg = out(reg) _,
-                subtimer = out(reg) subtimer,
-                byte0 = out(reg) num_timer0_overflows_byte0,
-                byte1 = out(reg) num_timer0_overflows_byte1,
-                byte2 = out(reg) num_timer0_overflows_byte2,
-                byte3 = out(reg) num_timer0_overflows_byte3,
-                inout("X") (&NUM_TIMER0_OVERFLOWS) as *const u32 as usize => _,
-                options(preserves_flags, readonly, nostack)
-            );
+        // The watchdog interrupt might have been triggered by a slow frame rather than a crash,
+        // in which case it must not be reported after the next reset.
+        hal::feed_watchdog();
+        unsafe {
+            CRASH_RECORD.write(watchdog::CrashRecord::NONE);
+        }
 
-            let num_timer0_overflows = u32::from_ne_bytes([
-                num_timer0_overflows_byte0,
-                num_timer0_overflows_byte1,
-                num_timer0_overflows_byte2,
-                num_timer0_overflows_byte3,
-            ]);
-
-            Duration::from_micros(
-                u64::from(num_timer0_overflows) * 1024
-                    + u64::from(subtimer) * 64 * 6250 / 100 / 1000,
-            )
-        };
+        let clock_value = read_clock(&mut clock);
+        frame_limiter.start_frame(clock_value);
 
         match (hal::read_bport::<4>(), button_is_pressed_since) {
             (false, Some(_)) => button_is_pressed_since = None,
-            (true, Some(ref v)) if (clock_value - *v).as_millis() >= 1500 => {
+            (true, Some(ref v))
+                if clock_value.saturating_sub(*v) >= Duration::from_millis(1500) =>
+            {
                 mode = leds::Mode::Off;
             }
             (false, None) | (true, Some(_)) => {}
             (true, None) => {
                 button_is_pressed_since = Some(clock_value);
 
-                // Mode cycle.
-                mode = match mode {
-                    leds::Mode::Off => leds::Mode::Neutral,
-                    leds::Mode::Neutral => leds::Mode::Fireplace,
-                    leds::Mode::Fireplace => leds::Mode::PartyCycle,
-                    leds::Mode::PartyCycle => leds::Mode::WholeStripAlternatingColor,
-                    leds::Mode::WholeStripAlternatingColor => leds::Mode::Neutral,
-                    _ => todo!(),
-                };
+                match last_button_press {
+                    // Pressing twice quickly starts the sleep timer, and cancels the change of
+                    // mode of the first press.
+                    Some((time, previous_mode))
+                        if clock_value.saturating_sub(time) < Duration::from_millis(400) =>
+                    {
+                        mode = previous_mode;
+                        sleep_timer =
+                            Some(sleep::SleepTimer::new(clock_value, sleep::BUTTON_DURATION));
+                        last_button_press = None;
+                    }
+                    _ => {
+                        last_button_press = Some((clock_value, mode));
+
+                        // Mode cycle.
+                        mode = mode.next_in_cycle();
+                    }
+                }
             }
         }
 
-        let mut data_size = 0usize;
-        let mut northwest_data_end = 0;
+        let ir_action = match hal::without_interrupts(|| unsafe { IR_EVENT.take() }) {
+            Some(ir::Event::Key { address, command }) => {
+                last_ir_action = ir::action_for_key(address, command);
+                // Reported so that the address and the commands of another remote can be found.
+                if last_ir_action.is_none() {
+                    write_serial("ir: unknown key ");
+                    write_serial_decimal(u32::from(address));
+                    write_serial(" ");
+                    write_serial_decimal(u32::from(command));
+                    write_serial("\n");
+                }
+                last_ir_action
+            }
+            Some(ir::Event::Repeat) => last_ir_action.filter(|a| a.repeats()),
+            None => None,
+        };
 
-        for strip in [leds::Strip::NorthWest, leds::Strip::SouthEast] {
-            debug_assert_eq!(data_size % 3, 0);
+        match ir_action {
+            Some(ir::Action::SelectMode(new_mode)) => mode = new_mode,
+            Some(ir::Action::Fireplace(params)) => {
+                mode = leds::Mode::Fireplace;
+                settings.fireplace = params;
+            }
+            Some(ir::Action::Off) => mode = leds::Mode::Off,
+            Some(ir::Action::BrightnessUp) => {
+                settings.brightness = settings.brightness.saturating_add(16)
+            }
+            Some(ir::Action::BrightnessDown) => {
+                settings.brightness = cmp::max(
+                    settings.brightness.saturating_sub(16),
+                    settings::MIN_BRIGHTNESS,
+                )
+            }
+            Some(ir::Action::SpeedUp) => {
+                settings.set_speed(mode, settings.speed(mode).saturating_add(8))
+            }
+            Some(ir::Action::SpeedDown) => {
+                settings.set_speed(mode, settings.speed(mode).saturating_sub(8))
+            }
+            Some(ir::Action::SaturationUp) => {
+                if let Some(params) = settings.rainbow_params_mut(mode) {
+                    params.saturation = params.saturation.saturating_add(16);
+                }
+            }
+            Some(ir::Action::SaturationDown) => {
+                if let Some(params) = settings.rainbow_params_mut(mode) {
+                    params.saturation = params.saturation.saturating_sub(16);
+                }
+            }
+            None => {}
+        }
 
-            northwest_data_end = data_size;
+        // Process the commands received through the serial port, if any.
+        while let Some((byte, lost_after)) = hal::without_interrupts(|| unsafe { SERIAL_RX.pop() })
+        {
+            let line = match serial_line_reader.push(byte, lost_after) {
+                Some(line) => line,
+                None => continue,
+            };
 
-            let mut iter = leds::led_colors(mode, clock_value, updates_wrapping_counter, strip) /*::led_colors_lerp(
-                    leds::Mode::Off,
-                    leds::Mode::Neutral,
-                    Duration::from_secs(50), // TODO:
-                    clock_value,
-                    strip,
-                )*/
-                .flat_map(|c| {
-                    // For some reason, the LED strip shows green as blue and vice versa, so we swap bytes.
-                    [c[0], c[2], c[1]].into_iter()
-                })
-                .fuse();
+            let result = line
+                .and_then(serial::parse_command)
+                .and_then(|command| match command {
+                    serial::Command::Mode(new_mode) => {
+                        mode = new_mode;
+                        Ok(())
+                    }
+                    serial::Command::Preset(preset) => {
+                        mode = settings.apply_preset(preset);
+                        Ok(())
+                    }
+                    serial::Command::Palette(palette) => {
+                        if settings.set_palette(mode, palette) {
+                            Ok(())
+                        } else {
+                            Err(serial::Error::NoPalette)
+                        }
+                    }
+                    serial::Command::Time(now) => {
+                        wall_clock = Some(time_of_day::WallClock::new(clock_value, now));
+                        Ok(())
+                    }
+                    serial::Command::Alarm(Some((wake_up_time, duration_minutes))) => {
+                        settings.sunrise = params::SunriseParams {
+                            wake_up_time,
+                            duration_minutes,
+                            alarm: true,
+                        };
+                        Ok(())
+                    }
+                    serial::Command::Alarm(None) => {
+                        settings.sunrise.alarm = false;
+                        Ok(())
+                    }
+                    serial::Command::Sleep(Some(minutes)) => {
+                        sleep_timer = Some(sleep::SleepTimer::new(
+                            clock_value,
+                            Duration::from_secs(u64::from(minutes) * 60),
+                        ));
+                        Ok(())
+                    }
+                    serial::Command::Sleep(None) => {
+                        sleep_timer = None;
+                        Ok(())
+                    }
+                    serial::Command::Fps(fps) => {
+                        frame_limiter.set_target_fps(fps);
+                        Ok(())
+                    }
+                    serial::Command::Status => {
+                        write_serial("mode ");
+                        write_serial(mode.info().name.as_str());
+                        write_serial(", ");
+                        write_serial_decimal(u32::from(frame_limiter.measured_fps()));
+                        write_serial(" fps (target ");
+                        write_serial_decimal(u32::from(frame_limiter.target_fps()));
+                        write_serial("), render ");
+                        write_serial_decimal(frame_limiter.render_micros());
+                        write_serial("us\n");
+                        Ok(())
+                    }
+                });
 
-            while let Some(byte) = iter.next() {
-                data_buffer[data_size] = byte;
-                data_size += 1;
+            match result {
+                Ok(()) => write_serial("ok\n"),
+                Err(error) => {
+                    write_serial("error: ");
+                    write_serial(error.message());
+                    write_serial("\n");
+                }
             }
         }
 
+        for (channel, (input, binding)) in
+            analog_inputs.iter_mut().zip(analog::BINDINGS).enumerate()
+        {
+            if let Some(parameter) = binding {
+                if let Some(value) = input.update(hal::read_adc(channel as u8)) {
+                    parameter.apply(&mut settings, mode, value);
+                }
+            }
+        }
+
+        effect_clock += clock_value
+            .checked_sub(previous_clock_value)
+            .unwrap_or(Duration::ZERO)
+            * u32::from(settings.speed(mode))
+            / u32::from(settings::NORMAL_SPEED);
+        previous_clock_value = clock_value;
+
+        // Sampling the microphone takes several milliseconds, so we only do it when needed.
+        let audio_frame = if matches!(mode, leds::Mode::Music) {
+            audio_analyzer
+                .analyze((0..audio::SAMPLES_PER_FRAME).map(|_| hal::read_adc(audio::MIC_CHANNEL)))
+        } else {
+            audio::AudioFrame::default()
+        };
+
+        // Fades the LEDs out, then switches them off, once the sleep timer is about to expire.
+        let sleep_brightness_scale = sleep::update(&mut sleep_timer, &mut mode, clock_value);
+
+        // Select the sunrise mode when the sunrise of the alarm starts. The mode can still be
+        // changed afterwards.
+        let time_of_day = wall_clock.map(|clock| clock.time_of_day(clock_value));
+        let is_sunrise = settings.sunrise.alarm
+            && time_of_day.map_or(false, |now| sunrise::is_rising(&settings.sunrise, now));
+        if is_sunrise && !was_sunrise {
+            mode = leds::Mode::Sunrise;
+        }
+        was_sunrise = is_sunrise;
+
+        let ctx = pattern::FrameContext {
+            clock_value: effect_clock,
+            updates_wrapping_counter,
+            settings: &settings,
+            audio: &audio_frame,
+            rng: &rng,
+            time_of_day,
+        };
+        unsafe {
+            CURRENT_MODE = mode;
+        }
+        (mode.info().render)(&ctx, &mut mode_state, &mut frame);
+
         updates_wrapping_counter = updates_wrapping_counter.wrapping_add(1);
 
-        debug_assert_eq!(data_size % 3, 0);
-        debug_assert_eq!(northwest_data_end % 3, 0);
+        // Uploading the data to strips with a single data line disables interrupts for a long
+        // time, which would corrupt the infrared frame being received, if any. We skip updating
+        // these strips during that time instead.
+        let ir_receiving =
+            hal::without_interrupts(|| unsafe { IR_DECODER.is_receiving(timer0_micros()) });
+        if mode != leds::Mode::Off {
+            leds_are_dark = false;
+        }
+        if !leds_are_dark {
+            let (northwest_frame, southeast_frame) =
+                frame.split_at_mut(leds::Strip::NorthWest.num_leds());
+            // The south-east strip is plugged at the end of the room, so its LEDs are in the
+            // reverse order compared to the frame. The frame is put back in order once sent.
+            southeast_frame.reverse();
+            let brightness = color::scale8(settings.brightness, sleep_brightness_scale);
+            let northwest_sent = send_strip::<2>(
+                leds::Strip::NorthWest.led_type(),
+                northwest_frame,
+                brightness,
+                ir_receiving,
+            );
+            let southeast_sent = send_strip::<0>(
+                leds::Strip::SouthEast.led_type(),
+                southeast_frame,
+                brightness,
+                ir_receiving,
+            );
+            southeast_frame.reverse();
+            leds_are_dark = northwest_sent && southeast_sent && mode == leds::Mode::Off;
+        }
+
+        // While the LEDs are off, there is nothing to animate, and the MCU sleeps as much as
+        // possible.
+        if leds_are_dark {
+            idle_until_woken();
+        }
+
+        // Sleeping rather than busy waiting, timer0 wakes up the MCU every 1024µs.
+        let next_frame_start = frame_limiter.end_frame(read_clock(&mut clock));
+        while read_clock(&mut clock) < next_frame_start {
+            hal::sleep_idle();
+        }
+    }
+}
+
+/// Sends the given colors scaled by the given brightness to a strip of the given type, unless
+/// sending them would disable interrupts while `ir_receiving` is `true`. Returns `true` if the
+/// colors have been sent. The colors are left as they were in any case.
+///
+/// Strips with a single data line are connected to the given PIN of port B. WS2811 strips must
+/// receive all their data at once, and have as many bytes per LED as colors do, so their data
+/// temporarily replaces the colors. RGBW strips have one more byte per LED, which wouldn't fit,
+/// but they only show the data once the line has stayed low for 80µs, much longer than it takes
+/// to encode an LED, so each LED is sent as it is encoded. APA102 strips are connected to the
+/// hardware SPI, and their data is sent as it is encoded.
+#[cfg(target_arch = "avr")]
+fn send_strip<const PIN: usize>(
+    led_type: output::LedType,
+    colors: &mut [color::Rgb],
+    brightness: u8,
+    ir_receiving: bool,
+) -> bool {
+    match led_type {
+        output::LedType::Ws2811 => {
+            if ir_receiving {
+                return false;
+            }
+            output::with_ws2811_data(colors, |data| {
+                upload_keeping_time::<PIN, 3>(data, brightness);
+            });
+        }
+        output::LedType::Sk6812Rgbw(_) => {
+            if ir_receiving {
+                return false;
+            }
+            for color in colors.iter() {
+                hal::upload_bport_data::<PIN, 4>(&[led_type.encode(*color, brightness)], 255);
+            }
+        }
+        output::LedType::Apa102 => {
+            for _ in 0..output::APA102_START_FRAME_LEN {
+                hal::write_spi(0);
+            }
+            for color in colors.iter() {
+                for byte in led_type.encode(*color, brightness) {
+                    hal::write_spi(byte);
+                }
+            }
+            for _ in 0..output::apa102_end_frame_len(colors.len()) {
+                hal::write_spi(0);
+            }
+        }
+    }
+    true
+}
 
-        hal::upload_bport_data::<2>(&data_buffer[..northwest_data_end]);
-        hal::upload_bport_data::<0>(&data_buffer[northwest_data_end..data_size]);
+/// Same as [`hal::upload_bport_data`], which keeps interrupts disabled for several overflows of
+/// timer0 when sending a whole strip. The overflows that its interrupt handler misses meanwhile
+/// are added to its counter, otherwise the clock would run slow while the LEDs are on.
+#[cfg(target_arch = "avr")]
+fn upload_keeping_time<const PIN: usize, const N: usize>(data: &[[u8; N]], brightness: u8) {
+    hal::without_interrupts(|| unsafe {
+        let timer0 = hal::read_timer0();
+        let overflow_pending = hal::is_timer0_overflow_pending();
+        hal::upload_bport_data::<PIN, N>(data, brightness);
+        let cycles = (data.len() * N) as u32 * hal::UPLOAD_CYCLES_PER_BYTE;
+        NUM_TIMER0_OVERFLOWS = NUM_TIMER0_OVERFLOWS.wrapping_add(clock::missed_overflows(
+            timer0,
+            overflow_pending,
+            hal::read_timer0(),
+            cycles,
+        ));
+    });
+}
 
-        // TODO: don't wait the full duration
-        ruduino::delay::delay_us(300);
+/// Returns the current value of the clock.
+#[cfg(target_arch = "avr")]
+fn read_clock(clock: &mut clock::Clock) -> Duration {
+    unsafe {
+        // In order to grab the clock value without running the risk of a race condition, we
+        // need to interrupts. For this reason, this is done directly in assembly code.
+
+        let sreg: u8;
+        let subtimer: u8;
+        let tifr0: u8;
+        let num_timer0_overflows_byte0: u8;
+        let num_timer0_overflows_byte1: u8;
+        let num_timer0_overflows_byte2: u8;
+        let num_timer0_overflows_byte3: u8;
+
+        core::arch::asm!(r#"
+            lds {sreg}, 0x5f  // SREG
+            cli
+            lds {subtimer}, 0x46
+            lds {tifr0}, 0x35
+            ld {byte0}, X+
+            ld {byte1}, X+
+            ld {byte2}, X+
+            ld {byte3}, X+
+            sts 0x5f, {sreg}
+            "#,
+            sreg = out(reg) _,
+            subtimer = out(reg) subtimer,
+            tifr0 = out(reg) tifr0,
+            byte0 = out(reg) num_timer0_overflows_byte0,
+            byte1 = out(reg) num_timer0_overflows_byte1,
+            byte2 = out(reg) num_timer0_overflows_byte2,
+            byte3 = out(reg) num_timer0_overflows_byte3,
+            inout("X") (&NUM_TIMER0_OVERFLOWS) as *const u32 as usize => _,
+            options(preserves_flags, readonly, nostack)
+        );
+
+        let num_timer0_overflows = u32::from_ne_bytes([
+            num_timer0_overflows_byte0,
+            num_timer0_overflows_byte1,
+            num_timer0_overflows_byte2,
+            num_timer0_overflows_byte3,
+        ]);
+
+        // Bit 0 of TIFR0 is TOV0, set when timer0 overflows until the interrupt handler runs.
+        clock.now(num_timer0_overflows, subtimer, tifr0 & 0b1 != 0)
     }
 }
 
+/// Sleeps until something might switch the LEDs back on: the push button being pressed, an event
+/// from the infrared remote, a byte received through the serial port, or a second having elapsed,
+/// so that the sunrise alarm can start.
+///
+/// The MCU can only use the idle sleep mode, as timer0 must keep running for the time of the day
+/// and the UART can't wake it up from deeper modes. It is still woken up every time timer0
+/// overflows, and goes back to sleep right away. This is also when the push button is polled, so
+/// it doesn't need an interrupt of its own.
+#[cfg(target_arch = "avr")]
+fn idle_until_woken() {
+    let start = hal::without_interrupts(|| unsafe { NUM_TIMER0_OVERFLOWS });
+    loop {
+        // An interrupt happening between the checks and going to sleep is only noticed after
+        // the next one, at most 1024µs later.
+        hal::sleep_idle();
+
+        let woken = hal::without_interrupts(|| unsafe {
+            IR_EVENT.is_some()
+                || !SERIAL_RX.is_empty()
+                || NUM_TIMER0_OVERFLOWS.wrapping_sub(start) >= 1000
+        });
+        if woken || hal::read_bport::<4>() {
+            return;
+        }
+    }
+}
+
+/// Returns the number of microseconds elapsed since timer0 has been enabled, wrapping around
+/// every 71 minutes.
+///
+/// Must only be called while interrupts are disabled.
+#[cfg(target_arch = "avr")]
+unsafe fn timer0_micros() -> u32 {
+    let timer0 = hal::read_timer0();
+    // Same as in `clock::Clock::now`, timer0 might have overflowed while interrupts are disabled,
+    // and the overflow must be counted, or the time would go back by 1024µs.
+    let num_overflows = if hal::is_timer0_overflow_pending() && timer0 < u8::MAX {
+        NUM_TIMER0_OVERFLOWS.wrapping_add(1)
+    } else {
+        NUM_TIMER0_OVERFLOWS
+    };
+    num_overflows
+        .wrapping_mul(1024)
+        .wrapping_add(u32::from(timer0) * 4)
+}
+
+#[cfg(target_arch = "avr")]
+#[no_mangle]
+pub unsafe extern "avr-interrupt" fn __vector_1() {
+    // INT0, triggered when the output of the infrared receiver changes.
+    if let Some(event) = IR_DECODER.on_edge(timer0_micros(), hal::read_dport::<2>()) {
+        IR_EVENT = Some(event);
+    }
+}
+
+/// Sends a string through the serial port. Blocks until all of it has been sent, except for the
+/// last byte.
+#[cfg(target_arch = "avr")]
+fn write_serial(s: &str) {
+    for byte in s.bytes() {
+        hal::write_uart(byte);
+    }
+}
+
+/// Sends a number in decimal through the serial port.
+#[cfg(target_arch = "avr")]
+fn write_serial_decimal(value: u32) {
+    let mut buffer = [0; 10];
+    for byte in serial::format_decimal(value, &mut buffer) {
+        hal::write_uart(*byte);
+    }
+}
+
+#[cfg(target_arch = "avr")]
+#[no_mangle]
+pub unsafe extern "avr-interrupt" fn __vector_6() {
+    // WDT, triggered when the main loop hasn't fed the watchdog in time. The MCU is reset at the
+    // next timeout.
+    CRASH_RECORD.write(watchdog::CrashRecord::new(
+        watchdog::Crash::Watchdog,
+        CURRENT_MODE,
+    ));
+}
+
+#[cfg(target_arch = "avr")]
 #[no_mangle]
 pub unsafe extern "avr-interrupt" fn __vector_16() {
     NUM_TIMER0_OVERFLOWS = NUM_TIMER0_OVERFLOWS.wrapping_add(1);
 }
 
+#[cfg(target_arch = "avr")]
+#[no_mangle]
+pub unsafe extern "avr-interrupt" fn __vector_18() {
+    // USART_RX, triggered when a byte has been received through the serial port.
+    SERIAL_RX.push(hal::read_uart());
+}
+
+#[cfg(target_arch = "avr")]
 #[no_mangle]
 pub unsafe extern "C" fn abort() {
+    // The watchdog resets the MCU.
+    loop {}
+}
+
+/// Signals the panic through the serial port and on the first LEDs, then lets the watchdog reset
+/// the MCU.
+#[cfg(target_arch = "avr")]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    // Number of LEDs of each strip showing the blink code.
+    const NUM_BLINKING_LEDS: usize = 8;
+    // Number of blinks of the blink code, and number of times it is shown.
+    const NUM_BLINKS: u8 = 3;
+    const NUM_REPEATS: u8 = 2;
+
+    // The panic might have happened in an interrupt handler, and the state they share with the
+    // main loop can't be trusted anymore.
+    hal::disable_interrupts();
+
+    unsafe {
+        CRASH_RECORD.write(watchdog::CrashRecord::new(
+            watchdog::Crash::Panic,
+            CURRENT_MODE,
+        ));
+    }
+
+    // The location of the panic isn't sent: its file names would take room in the RAM, where
+    // constants are copied at startup. `build.sh` doesn't even generate them.
+    if hal::is_uart_enabled() {
+        write_serial("error: panic\n");
+    }
+
+    let show = |color: color::Rgb| {
+        send_strip::<2>(
+            leds::Strip::NorthWest.led_type(),
+            &mut [color; NUM_BLINKING_LEDS],
+            255,
+            false,
+        );
+        send_strip::<0>(
+            leds::Strip::SouthEast.led_type(),
+            &mut [color; NUM_BLINKING_LEDS],
+            255,
+            false,
+        );
+    };
+
+    // Three red blinks and a pause, which no mode looks like.
+    for _ in 0..NUM_REPEATS {
+        for _ in 0..NUM_BLINKS {
+            show(color::Rgb::new(64, 0, 0));
+            hal::busy_wait_ms(200);
+            show(color::Rgb::BLACK);
+            hal::busy_wait_ms(200);
+        }
+        hal::busy_wait_ms(600);
+    }
+
+    // With interrupts disabled, the `WDT` interrupt can't run, and the watchdog would never go
+    // on to reset the MCU.
+    hal::disable_watchdog_interrupt();
     loop {}
 }
@@ -0,0 +1,234 @@
+// Conversion of the frames into the data expected by the LED strips.
+//
+// The strips of the room are WS2811 strips, with 3 bytes per LED. SK6812 RGBW strips, which have
+// an additional white LED, are also supported: the white part of each color is shown with the
+// white LED, which gives much better whites than mixing red, green and blue.
+//
+// APA102 and SK9822 strips have a clock line and are driven with the hardware SPI, which doesn't
+// need interrupts to be disabled. Each of their LEDs also has a 5 bits global brightness, used to
+// keep more precision than 8 bits per component when the LEDs are dim.
+
+use core::slice;
+
+use crate::{color::Rgb, leds::NEUTRAL_WHITE};
+
+/// Type of the LEDs of a strip. See [`crate::leds::Strip::led_type`].
+// Not all types are used by the strips currently installed.
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LedType {
+    Ws2811,
+    /// RGBW strip, whose white LEDs have the given color temperature.
+    Sk6812Rgbw(WhiteTemperature),
+    /// APA102 or SK9822 strip, connected to the hardware SPI. Only one strip can be connected to
+    /// it.
+    Apa102,
+}
+
+impl LedType {
+    /// Returns the bytes to send for an LED of the given color scaled by the given brightness,
+    /// in the order expected by the strip. WS2811 strips only use the first 3 bytes.
+    pub fn encode(self, color: Rgb, brightness: u8) -> [u8; 4] {
+        match self {
+            LedType::Ws2811 => {
+                let color = color.scale(brightness);
+                // For some reason, the LED strips show green as blue and vice versa, so we swap
+                // bytes.
+                [color.r(), color.b(), color.g(), 0]
+            }
+            LedType::Sk6812Rgbw(temperature) => {
+                let (color, white) = rgb_to_rgbw(color.scale(brightness), temperature.color());
+                [color.g(), color.r(), color.b(), white]
+            }
+            LedType::Apa102 => {
+                let (color, global_brightness) = apa102_brightness(color, brightness);
+                [
+                    0b1110_0000 | global_brightness,
+                    color.b(),
+                    color.g(),
+                    color.r(),
+                ]
+            }
+        }
+    }
+}
+
+/// Number of zero bytes sent before the data of an APA102 strip.
+#[cfg(target_arch = "avr")]
+pub const APA102_START_FRAME_LEN: usize = 4;
+
+/// Returns the number of zero bytes sent after the data of an APA102 strip of the given number
+/// of LEDs.
+///
+/// Each LED delays the data by half a clock cycle, so the data only reaches the last LEDs with
+/// `num_leds / 2` more clock cycles. SK9822 strips also only show the new colors after an
+/// additional frame of 4 bytes.
+#[cfg(target_arch = "avr")]
+pub const fn apa102_end_frame_len(num_leds: usize) -> usize {
+    4 + (num_leds + 15) / 16
+}
+
+/// Splits the color scaled by the given brightness into components and a 5 bits global
+/// brightness. The global brightness is as low as possible, so that the components are as high
+/// as possible, and keep their precision.
+fn apa102_brightness(color: Rgb, brightness: u8) -> (Rgb, u8) {
+    // Highest component of the scaled color, multiplied by 255.
+    let max_component = u32::from(color.r().max(color.g()).max(color.b())) * u32::from(brightness);
+    if max_component == 0 {
+        return (Rgb::BLACK, 0);
+    }
+
+    let global_brightness = (max_component * 31 + 255 * 255 - 1) / (255 * 255);
+    let color = color.map(|component| {
+        (u32::from(component) * u32::from(brightness) * 31 / (255 * global_brightness)) as u8
+    });
+    (color, global_brightness as u8)
+}
+
+/// Color temperature of the white LEDs of RGBW strips.
+///
+/// The colors of the frames are those of the WS2811 strips, on which [`NEUTRAL_WHITE`] looks
+/// white, so the color of each temperature is expressed on the same scale.
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WhiteTemperature {
+    /// Around 3000K.
+    Warm,
+    /// Around 4500K.
+    Neutral,
+    /// Around 6500K.
+    Cool,
+}
+
+impl WhiteTemperature {
+    /// Returns the color of the frames that the white LED looks like when fully on.
+    pub const fn color(self) -> Rgb {
+        match self {
+            WhiteTemperature::Warm => NEUTRAL_WHITE,
+            WhiteTemperature::Neutral => Rgb::new(140, 70, 40),
+            WhiteTemperature::Cool => Rgb::new(140, 90, 65),
+        }
+    }
+}
+
+/// Splits a color into the part that can be shown with a white LED of the given color, and the
+/// rest, which is shown with the red, green and blue LEDs. Returns the rest and the brightness
+/// of the white LED.
+pub fn rgb_to_rgbw(color: Rgb, white: Rgb) -> (Rgb, u8) {
+    // The white LED is as bright as possible without exceeding any component of the color.
+    let brightness = color
+        .0
+        .iter()
+        .zip(white.0)
+        .filter(|(_, white)| *white != 0)
+        .map(|(component, white)| u16::from(*component) * 255 / u16::from(white))
+        .min()
+        .unwrap_or(0)
+        .min(255);
+
+    let rest = color.zip_map(white, |component, white| {
+        component - (u16::from(white) * brightness / 255) as u8
+    });
+    (rest, brightness as u8)
+}
+
+/// Calls `f` with the data of the LEDs of a WS2811 strip showing the given colors, before their
+/// brightness is applied. The data is written in place of the colors, which are restored
+/// afterwards, so that WS2811 strips don't need a buffer of their own.
+pub fn with_ws2811_data<T>(colors: &mut [Rgb], f: impl FnOnce(&[[u8; 3]]) -> T) -> T {
+    swap_green_and_blue(colors);
+    // `Rgb` is a transparent wrapper around `[u8; 3]`.
+    let out = f(unsafe { slice::from_raw_parts(colors.as_ptr().cast::<[u8; 3]>(), colors.len()) });
+    swap_green_and_blue(colors);
+    out
+}
+
+/// Swaps the green and blue components of the given colors, see [`LedType::encode`].
+fn swap_green_and_blue(colors: &mut [Rgb]) {
+    for color in colors {
+        color.0.swap(1, 2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPERATURES: [WhiteTemperature; 3] = [
+        WhiteTemperature::Warm,
+        WhiteTemperature::Neutral,
+        WhiteTemperature::Cool,
+    ];
+
+    #[test]
+    fn rgbw_extremes() {
+        for temperature in TEMPERATURES {
+            let white = temperature.color();
+            assert_eq!(rgb_to_rgbw(Rgb::BLACK, white), (Rgb::BLACK, 0));
+            assert_eq!(rgb_to_rgbw(white, white), (Rgb::BLACK, 255));
+            // Pure colors have nothing in common with the white LED.
+            for color in [
+                Rgb::new(255, 0, 0),
+                Rgb::new(0, 255, 0),
+                Rgb::new(0, 0, 255),
+            ] {
+                assert_eq!(rgb_to_rgbw(color, white), (color, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn rgbw_adds_up() {
+        for temperature in TEMPERATURES {
+            let white = temperature.color();
+            for r in (0..=255).step_by(15) {
+                for g in (0..=255).step_by(15) {
+                    for b in (0..=255).step_by(15) {
+                        let color = Rgb::new(r, g, b);
+                        let (rest, brightness) = rgb_to_rgbw(color, white);
+                        let shown = rest.zip_map(white, |rest, white| {
+                            rest + (u16::from(white) * u16::from(brightness) / 255) as u8
+                        });
+                        assert_eq!(shown, color, "{color:?}");
+
+                        // The white LED can't be brighter without exceeding a component.
+                        if brightness < 255 {
+                            let brighter = u16::from(brightness) + 1;
+                            assert!(
+                                color.0.iter().zip(white.0).any(|(component, white)| {
+                                    u16::from(*component) * 255 < u16::from(white) * brighter
+                                }),
+                                "{color:?}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn encode() {
+        let color = Rgb::new(200, 100, 50);
+        assert_eq!(LedType::Ws2811.encode(color, 255), [200, 50, 100, 0]);
+        assert_eq!(LedType::Ws2811.encode(color, 127), [100, 25, 50, 0]);
+
+        let rgbw = LedType::Sk6812Rgbw(WhiteTemperature::Warm);
+        assert_eq!(rgbw.encode(NEUTRAL_WHITE, 255), [0, 0, 0, 255]);
+        assert_eq!(rgbw.encode(NEUTRAL_WHITE, 0), [0, 0, 0, 0]);
+        let (rest, white) = rgb_to_rgbw(color.scale(127), NEUTRAL_WHITE);
+        assert_eq!(
+            rgbw.encode(color, 127),
+            [rest.g(), rest.r(), rest.b(), white]
+        );
+    }
+
+    #[test]
+    fn ws2811_in_place() {
+        let mut colors = [Rgb::new(200, 100, 50), Rgb::new(1, 2, 3)];
+        let data = with_ws2811_data(&mut colors, |data| data.to_vec());
+        assert_eq!(data, [[200, 50, 100], [1, 3, 2]]);
+        // The colors are left as they were.
+        assert_eq!(colors, [Rgb::new(200, 100, 50), Rgb::new(1, 2, 3)]);
+    }
+}
@@ -0,0 +1,117 @@
+// Color palettes, similar to the `CRGBPalette16` of the FastLED library.
+//
+// A palette is a list of 16 colors spread evenly over the range of a `u8`. Looking up a color
+// between two entries interpolates between them, which makes it possible to go through a
+// palette smoothly.
+
+use crate::{
+    color::Rgb,
+    progmem::{progmem, Name},
+};
+
+/// Number of colors in a [`Palette`].
+pub const PALETTE_LEN: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Palette(pub [Rgb; PALETTE_LEN]);
+
+impl Palette {
+    /// Black, dark reds, and a few bright orange and white spots.
+    pub const LAVA: Self = Palette::from_hex([
+        0x000000, 0x800000, 0x000000, 0x800000, 0x8B0000, 0x8B0000, 0x800000, 0x8B0000, 0x8B0000,
+        0x8B0000, 0xFF0000, 0xFFA500, 0xFFFFFF, 0xFFA500, 0xFF0000, 0x8B0000,
+    ]);
+
+    /// Blues and sea greens.
+    pub const OCEAN: Self = Palette::from_hex([
+        0x191970, 0x00008B, 0x191970, 0x000080, 0x00008B, 0x0000CD, 0x2E8B57, 0x008080, 0x5F9EA0,
+        0x0000FF, 0x008B8B, 0x6495ED, 0x7FFFD4, 0x2E8B57, 0x00FFFF, 0x87CEFA,
+    ]);
+
+    /// Greens and yellow greens.
+    pub const FOREST: Self = Palette::from_hex([
+        0x006400, 0x006400, 0x556B2F, 0x006400, 0x008000, 0x228B22, 0x6B8E23, 0x008000, 0x2E8B57,
+        0x66CDAA, 0x32CD32, 0x9ACD32, 0x90EE90, 0x7CFC00, 0x66CDAA, 0x228B22,
+    ]);
+
+    /// Rainbow without the greens.
+    pub const PARTY: Self = Palette::from_hex([
+        0x5500AB, 0x84007C, 0xB5004B, 0xE5001B, 0xE81700, 0xB84700, 0xAB7700, 0xABAB00, 0xAB5500,
+        0xDD2200, 0xF2000E, 0xC2003E, 0x8F0071, 0x5F00A1, 0x2F00D0, 0x0007F9,
+    ]);
+
+    /// Black, then red, yellow and white, like the color of a hot object.
+    pub const HEAT: Self = Palette::from_hex([
+        0x000000, 0x330000, 0x660000, 0x990000, 0xCC0000, 0xFF0000, 0xFF3300, 0xFF6600, 0xFF9900,
+        0xFFCC00, 0xFFFF00, 0xFFFF33, 0xFFFF66, 0xFFFF99, 0xFFFFCC, 0xFFFFFF,
+    ]);
+
+    /// Dark red, orange, then purple and deep blue.
+    pub const SUNSET: Self = Palette::from_hex([
+        0x780000, 0xA61100, 0xD23800, 0xFF6800, 0xD33F09, 0xA71612, 0x900F2F, 0x79074C, 0x630067,
+        0x4C006F, 0x350076, 0x1F007D, 0x0E0085, 0x0A008E, 0x050097, 0x0000A0,
+    ]);
+
+    /// All the hues, the same as [`crate::color::Hsv::to_rgb_rainbow`].
+    pub const RAINBOW: Self = Palette::from_hex([
+        0xFF0000, 0xD52A00, 0xAB5500, 0xAB7F00, 0xABAB00, 0x56D500, 0x00FF00, 0x00D52A, 0x00AB55,
+        0x0056AA, 0x0000FF, 0x2A00D5, 0x5500AB, 0x7F0081, 0xAB0055, 0xD5002B,
+    ]);
+
+    /// The 6 dim colors that the whole strip originally alternated between, repeated to fill the
+    /// palette. Meant to be used with only its first 6 entries.
+    pub const ALTERNATING: Self = Palette::from_hex([
+        0x800000, 0x500020, 0x000080, 0x148032, 0x008000, 0x404000, 0x800000, 0x500020, 0x000080,
+        0x148032, 0x008000, 0x404000, 0x800000, 0x500020, 0x000080, 0x148032,
+    ]);
+
+    /// The 7 dim colors of the original segments, repeated to fill the palette. Meant to be used
+    /// with only its first 7 entries.
+    pub const SEGMENTS: Self = Palette::from_hex([
+        0x800000, 0x643232, 0x004040, 0x404000, 0x000080, 0x008000, 0x400040, 0x800000, 0x643232,
+        0x004040, 0x404000, 0x000080, 0x008000, 0x400040, 0x800000, 0x643232,
+    ]);
+
+    /// Builds a palette from colors written as `0xRRGGBB`.
+    pub const fn from_hex(colors: [u32; PALETTE_LEN]) -> Self {
+        let mut out = [Rgb::BLACK; PALETTE_LEN];
+        let mut n = 0;
+        while n < PALETTE_LEN {
+            out[n] = Rgb::new(
+                (colors[n] >> 16) as u8,
+                (colors[n] >> 8) as u8,
+                colors[n] as u8,
+            );
+            n += 1;
+        }
+        Palette(out)
+    }
+
+    /// Returns the built-in palette with the given name, if any.
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        (0..BUILT_IN.len())
+            .find(|index| BUILT_IN.load_part(*index, |(n, _)| n).as_str().as_bytes() == name)
+            .map(|index| BUILT_IN.load_part(index, |(_, palette)| palette))
+    }
+
+    /// Returns the color at the given position, where 256 would be a full turn. Positions
+    /// between two entries are interpolated, the last entry being followed by the first one.
+    pub fn get(&self, index: u8) -> Rgb {
+        let entry = usize::from(index >> 4);
+        let next = (entry + 1) % PALETTE_LEN;
+        self.0[entry].lerp(self.0[next], (index & 0x0f) << 4)
+    }
+}
+
+progmem! {
+    /// Built-in palettes and their names.
+    pub static BUILT_IN: [(Name, Palette); 7] = [
+        (Name::new("lava"), Palette::LAVA),
+        (Name::new("ocean"), Palette::OCEAN),
+        (Name::new("forest"), Palette::FOREST),
+        (Name::new("party"), Palette::PARTY),
+        (Name::new("heat"), Palette::HEAT),
+        (Name::new("sunset"), Palette::SUNSET),
+        (Name::new("rainbow"), Palette::RAINBOW),
+    ];
+}
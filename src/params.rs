@@ -0,0 +1,288 @@
+// Parameters of the effects of the modes.
+//
+// Each mode whose look can be tweaked is backed by a parameter struct, stored in the settings.
+// The constants of each struct are presets, which can be used as-is or as a starting point. Those
+// listed in `PRESETS` can also be selected by name through the serial port.
+
+use crate::{
+    color::Rgb,
+    palette::Palette,
+    progmem::{progmem, Name},
+    settings::NORMAL_SPEED,
+    time_of_day::TimeOfDay,
+};
+
+/// Parameters of [`crate::leds::Mode::Fireplace`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FireplaceParams {
+    /// Color of the LEDs at the lowest intensity.
+    pub dim_color: Rgb,
+    /// Color of the LEDs at the highest intensity.
+    pub bright_color: Rgb,
+    /// Range of intensity of the waves that move through the LEDs. Must be ordered.
+    pub intensity_range: (u8, u8),
+    /// Rotation of the hue of the colors, where 256 would be a full turn.
+    pub hue_shift: u8,
+    /// See [`crate::settings::Settings::speed`].
+    pub speed: u8,
+}
+
+impl FireplaceParams {
+    pub const ORANGE: Self = FireplaceParams {
+        dim_color: Rgb::new(9, 3, 0),
+        bright_color: Rgb::new(40, 5, 0),
+        intensity_range: (0, 255),
+        hue_shift: 0,
+        speed: NORMAL_SPEED,
+    };
+
+    pub const BLUE: Self = FireplaceParams {
+        dim_color: Rgb::new(0, 2, 9),
+        bright_color: Rgb::new(5, 12, 40),
+        intensity_range: (0, 255),
+        hue_shift: 0,
+        speed: NORMAL_SPEED,
+    };
+}
+
+/// Parameters of [`crate::leds::Mode::SegmentLights`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SegmentLightsParams {
+    /// Colors of the successive segments, each segment using the next entry of the palette.
+    pub palette: Palette,
+    /// Number of entries of the palette that are used, starting from the first one. Between 1 and
+    /// [`crate::palette::PALETTE_LEN`].
+    pub num_colors: u8,
+    /// Number of LEDs of each segment. Never 0.
+    pub segment_width: u8,
+    /// See [`crate::settings::Settings::speed`].
+    pub speed: u8,
+}
+
+impl SegmentLightsParams {
+    pub const CLASSIC: Self = SegmentLightsParams {
+        palette: Palette::SEGMENTS,
+        num_colors: 7,
+        segment_width: 6,
+        speed: NORMAL_SPEED,
+    };
+}
+
+/// Parameters of [`crate::leds::Mode::WholeStripAlternatingColor`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AlternatingColorParams {
+    /// Colors that the LEDs go through, spending 2 seconds on each entry of the palette.
+    pub palette: Palette,
+    /// Number of entries of the palette that are used, starting from the first one. Between 1 and
+    /// [`crate::palette::PALETTE_LEN`].
+    pub num_colors: u8,
+    /// See [`crate::settings::Settings::speed`].
+    pub speed: u8,
+}
+
+impl AlternatingColorParams {
+    pub const CLASSIC: Self = AlternatingColorParams {
+        palette: Palette::ALTERNATING,
+        num_colors: 6,
+        speed: NORMAL_SPEED,
+    };
+}
+
+/// Parameters of the rainbow modes, such as [`crate::leds::Mode::Rainbow`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RainbowParams {
+    /// Saturation of the colors, where 0 is white and 255 the pure hues.
+    pub saturation: u8,
+    /// See [`crate::settings::Settings::speed`].
+    pub speed: u8,
+}
+
+impl RainbowParams {
+    pub const VIVID: Self = RainbowParams {
+        saturation: 255,
+        speed: NORMAL_SPEED,
+    };
+
+    pub const PASTEL: Self = RainbowParams {
+        saturation: 160,
+        speed: NORMAL_SPEED,
+    };
+}
+
+/// Parameters of [`crate::leds::Mode::Fire`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FireParams {
+    /// How fast the flames cool down. Higher values give shorter flames.
+    pub cooling: u8,
+    /// Chance out of 255 that a new spark is lit at the bottom of each flame, at each step of
+    /// the simulation.
+    pub sparking: u8,
+    /// Colors of the heat, going from cold to hot.
+    pub palette: Palette,
+    /// See [`crate::settings::Settings::speed`].
+    pub speed: u8,
+}
+
+impl FireParams {
+    pub const CLASSIC: Self = FireParams {
+        cooling: 55,
+        sparking: 120,
+        palette: Palette::HEAT,
+        speed: NORMAL_SPEED,
+    };
+
+    pub const EMBERS: Self = FireParams {
+        cooling: 90,
+        sparking: 60,
+        palette: Palette::LAVA,
+        speed: NORMAL_SPEED,
+    };
+}
+
+/// Parameters of [`crate::leds::Mode::Twinkle`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TwinkleParams {
+    /// Color of the LEDs that aren't twinkling.
+    pub base_color: Rgb,
+    /// Colors of the twinkling LEDs. Each LED always uses the same entry.
+    pub palette: Palette,
+    /// Chance out of 255 that an LED starts twinkling, at each step of the animation.
+    pub density: u8,
+    /// How fast the LEDs brighten and fade. A twinkle lasts `256 / fade_speed` steps. 0 is treated
+    /// as 1.
+    pub fade_speed: u8,
+    /// See [`crate::settings::Settings::speed`].
+    pub speed: u8,
+}
+
+impl TwinkleParams {
+    pub const STARS: Self = TwinkleParams {
+        base_color: Rgb::new(0, 0, 4),
+        palette: Palette::OCEAN,
+        density: 64,
+        fade_speed: 4,
+        speed: NORMAL_SPEED,
+    };
+
+    pub const FAIRY_LIGHTS: Self = TwinkleParams {
+        base_color: Rgb::new(4, 1, 0),
+        palette: Palette::PARTY,
+        density: 160,
+        fade_speed: 8,
+        speed: NORMAL_SPEED,
+    };
+}
+
+/// Parameters of [`crate::leds::Mode::Meteor`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MeteorParams {
+    /// Number of comets, spread evenly around the room. 0 switches all the LEDs off.
+    pub count: u8,
+    /// Number of LEDs after which the trail of a comet has faded out.
+    pub trail_length: u8,
+    pub color: Rgb,
+    /// See [`crate::settings::Settings::speed`].
+    pub speed: u8,
+}
+
+impl MeteorParams {
+    pub const WHITE: Self = MeteorParams {
+        count: 3,
+        trail_length: 20,
+        color: Rgb::new(180, 200, 255),
+        speed: NORMAL_SPEED,
+    };
+
+    pub const EMBER: Self = MeteorParams {
+        count: 1,
+        trail_length: 60,
+        color: Rgb::new(255, 60, 0),
+        speed: NORMAL_SPEED,
+    };
+}
+
+/// Parameters of [`crate::leds::Mode::Sunrise`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SunriseParams {
+    /// Time of the day at which the sunrise ends.
+    pub wake_up_time: TimeOfDay,
+    /// Duration of the sunrise, between 10 and 60 minutes.
+    pub duration_minutes: u8,
+    /// If `true`, the sunrise mode is automatically selected when the sunrise starts.
+    pub alarm: bool,
+}
+
+impl SunriseParams {
+    pub const SEVEN_AM: Self = SunriseParams {
+        wake_up_time: match TimeOfDay::from_hms(7, 0, 0) {
+            Some(time) => time,
+            None => unreachable!(),
+        },
+        duration_minutes: 30,
+        alarm: false,
+    };
+}
+
+/// Parameters of a mode that can be selected by name. See [`PRESETS`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Preset {
+    Fireplace(FireplaceParams),
+    Fire(FireParams),
+    Twinkle(TwinkleParams),
+    Meteor(MeteorParams),
+}
+
+impl Preset {
+    /// Returns the preset with the given name, if any.
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        (0..PRESETS.len())
+            .find(|index| PRESETS.load_part(*index, |(n, _)| n).as_str().as_bytes() == name)
+            .map(|index| PRESETS.load_part(index, |(_, preset)| preset))
+    }
+}
+
+progmem! {
+    /// Presets that can be selected by name.
+    pub static PRESETS: [(Name, Preset); 8] = [
+        (Name::new("orange-fireplace"), Preset::Fireplace(FireplaceParams::ORANGE)),
+        (Name::new("blue-fireplace"), Preset::Fireplace(FireplaceParams::BLUE)),
+        (Name::new("classic-fire"), Preset::Fire(FireParams::CLASSIC)),
+        (Name::new("embers"), Preset::Fire(FireParams::EMBERS)),
+        (Name::new("stars"), Preset::Twinkle(TwinkleParams::STARS)),
+        (Name::new("fairy-lights"), Preset::Twinkle(TwinkleParams::FAIRY_LIGHTS)),
+        (Name::new("white-meteors"), Preset::Meteor(MeteorParams::WHITE)),
+        (Name::new("ember-meteor"), Preset::Meteor(MeteorParams::EMBER)),
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets() {
+        assert_eq!(
+            Preset::from_name(b"embers"),
+            Some(Preset::Fire(FireParams::EMBERS))
+        );
+        assert_eq!(
+            Preset::from_name(b"blue-fireplace"),
+            Some(Preset::Fireplace(FireplaceParams::BLUE))
+        );
+        assert_eq!(
+            Preset::from_name(b"fairy-lights"),
+            Some(Preset::Twinkle(TwinkleParams::FAIRY_LIGHTS))
+        );
+        assert_eq!(
+            Preset::from_name(b"ember-meteor"),
+            Some(Preset::Meteor(MeteorParams::EMBER))
+        );
+        assert_eq!(Preset::from_name(b"ember"), None);
+        assert_eq!(Preset::from_name(b""), None);
+
+        for index in 0..PRESETS.len() {
+            let (name, preset) = PRESETS.load(index);
+            assert_eq!(Preset::from_name(name.as_str().as_bytes()), Some(preset));
+        }
+    }
+}
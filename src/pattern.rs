@@ -0,0 +1,425 @@
+// Registry of the modes, and the trait implemented by the code that generates their colors.
+
+use core::time::Duration;
+
+use crate::{
+    audio::AudioFrame,
+    color::Rgb,
+    fire::{self, FireState},
+    layers::{self, Scene},
+    leds::{self, Mode, TOTAL_LEDS},
+    progmem::{progmem, Name},
+    random::Rng,
+    settings::Settings,
+    sunrise,
+    time_of_day::TimeOfDay,
+    twinkle::{self, TwinkleState},
+};
+
+/// Colors of all the LEDs of the room, in layout order. See [`leds::wall`].
+pub type Frame = [Rgb; TOTAL_LEDS];
+
+/// Everything that a [`Pattern`] can use to generate the colors of a frame.
+pub struct FrameContext<'a> {
+    /// All the timings of the effects are derived from this clock, which runs faster or slower
+    /// than the real time depending on the speed of the mode (see [`Settings::speed`]).
+    pub clock_value: Duration,
+    /// Incremented by one every frame.
+    pub updates_wrapping_counter: u8,
+    pub settings: &'a Settings,
+    pub audio: &'a AudioFrame,
+    /// Source of randomness, for the patterns that need it.
+    pub rng: &'a Rng,
+    /// Current time of the day, or `None` if it hasn't been set.
+    pub time_of_day: Option<TimeOfDay>,
+}
+
+pub trait Pattern: Sync {
+    /// Writes the colors of all the LEDs to `frame`.
+    ///
+    /// When this function is called, `frame` holds the colors rendered for the previous frame,
+    /// before brightness, or black at startup. Patterns can build on them for effects such as blurs
+    /// or fading trails. The previous frame might have been rendered by another mode, right after
+    /// the mode changed. Patterns that need to remember anything else between frames can keep it
+    /// in `state`.
+    fn render(&self, ctx: &FrameContext, state: &mut ModeState, frame: &mut Frame);
+}
+
+/// Turns the clock of the effects into a number of steps of fixed duration, for the patterns
+/// that simulate something.
+#[derive(Debug, Clone)]
+pub struct Stepper {
+    /// Value of the clock of the effects at the last step.
+    last_step: Duration,
+}
+
+impl Stepper {
+    /// Duration of a step, measured with the clock of the effects.
+    pub const STEP_DURATION: Duration = Duration::from_millis(16);
+
+    /// Maximum number of steps performed during one frame. Prevents a long pause, for example
+    /// when the mode has just been selected, from making the next frame very long to render.
+    pub const MAX_STEPS_PER_FRAME: u32 = 4;
+
+    pub const fn new() -> Self {
+        Stepper {
+            last_step: Duration::ZERO,
+        }
+    }
+
+    /// Returns the number of steps to perform given the current value of the clock of the
+    /// effects.
+    pub fn advance(&mut self, clock_value: Duration) -> u32 {
+        let elapsed = clock_value
+            .checked_sub(self.last_step)
+            .unwrap_or(Duration::ZERO);
+        // Compared before dividing, so that a very long pause can't be truncated into a few
+        // steps, and so that the division is on a `u32` instead of the `u128` of `as_millis`.
+        if elapsed >= Self::STEP_DURATION * (Self::MAX_STEPS_PER_FRAME + 1) {
+            self.last_step = clock_value;
+            Self::MAX_STEPS_PER_FRAME
+        } else {
+            let num_steps = elapsed.subsec_millis() / Self::STEP_DURATION.subsec_millis();
+            self.last_step += Self::STEP_DURATION * num_steps;
+            num_steps
+        }
+    }
+}
+
+/// State kept between frames by the modes that need it.
+///
+/// Only the state of the mode currently being displayed is kept, and the states of all the
+/// modes share the same memory, as we can't afford to reserve memory for each of them.
+#[derive(Debug, Clone)]
+pub enum ModeState {
+    None,
+    Fire(FireState),
+    Twinkle(TwinkleState),
+}
+
+impl ModeState {
+    /// Returns the state of [`Mode::Fire`], initializing it if the state is of another mode.
+    pub fn fire(&mut self) -> &mut FireState {
+        if !matches!(self, ModeState::Fire(_)) {
+            *self = ModeState::Fire(FireState::new());
+        }
+
+        match self {
+            ModeState::Fire(state) => state,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the state of [`Mode::Twinkle`], initializing it if the state is of another mode.
+    pub fn twinkle(&mut self) -> &mut TwinkleState {
+        if !matches!(self, ModeState::Twinkle(_)) {
+            *self = ModeState::Twinkle(TwinkleState::new());
+        }
+
+        match self {
+            ModeState::Twinkle(state) => state,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct ModeInfo {
+    pub mode: Mode,
+    /// Human-readable name of the mode.
+    pub name: Name,
+    /// Renders the mode, see [`Pattern::render`]. A `&dyn Pattern` would need a vtable, which
+    /// would be copied to the RAM.
+    pub render: fn(&FrameContext, &mut ModeState, &mut Frame),
+    /// If `true`, the mode can be selected by pressing the push button.
+    pub in_cycle: bool,
+}
+
+progmem! {
+    /// List of all the modes. The push button goes through the modes that are in the cycle, in
+    /// the order of this list.
+    pub static MODES: [ModeInfo; 19] = [
+        ModeInfo {
+            mode: Mode::Off,
+            name: Name::new("off"),
+            render: |ctx, state, frame| leds::Off.render(ctx, state, frame),
+            in_cycle: false,
+        },
+        ModeInfo {
+            mode: Mode::Neutral,
+            name: Name::new("neutral"),
+            render: |ctx, state, frame| leds::Neutral.render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::Fireplace,
+            name: Name::new("fireplace"),
+            render: |ctx, state, frame| leds::Fireplace.render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::SegmentLights,
+            name: Name::new("segment-lights"),
+            render: |ctx, state, frame| leds::SegmentLights.render(ctx, state, frame),
+            in_cycle: false,
+        },
+        ModeInfo {
+            mode: Mode::PartyCycle,
+            name: Name::new("party"),
+            render: |ctx, state, frame| leds::PartyCycle.render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::WholeStripAlternatingColor,
+            name: Name::new("alternating-color"),
+            render: |ctx, state, frame| leds::WholeStripAlternatingColor.render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::Music,
+            name: Name::new("music"),
+            render: |ctx, state, frame| leds::Music.render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::SideRotation,
+            name: Name::new("side-rotation"),
+            render: |ctx, state, frame| Scene(layers::SIDE_ROTATION).render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::SlowDrift,
+            name: Name::new("slow-drift"),
+            render: |ctx, state, frame| Scene(layers::SLOW_DRIFT).render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::Cursor,
+            name: Name::new("cursor"),
+            render: |ctx, state, frame| Scene(layers::CURSOR).render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::Strobe,
+            name: Name::new("strobe"),
+            render: |ctx, state, frame| Scene(layers::STROBE).render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::Rainbow,
+            name: Name::new("rainbow"),
+            render: |ctx, state, frame| leds::Rainbow.render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::RainbowGlitter,
+            name: Name::new("rainbow-glitter"),
+            render: |ctx, state, frame| leds::RainbowGlitter.render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::TheatreChase,
+            name: Name::new("theatre-chase"),
+            render: |ctx, state, frame| leds::TheatreChase.render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::HueCycle,
+            name: Name::new("hue-cycle"),
+            render: |ctx, state, frame| leds::HueCycle.render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::Fire,
+            name: Name::new("fire"),
+            render: |ctx, state, frame| fire::Fire.render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::Twinkle,
+            name: Name::new("twinkle"),
+            render: |ctx, state, frame| twinkle::Twinkle.render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::Meteor,
+            name: Name::new("meteor"),
+            render: |ctx, state, frame| leds::Meteor.render(ctx, state, frame),
+            in_cycle: true,
+        },
+        ModeInfo {
+            mode: Mode::Sunrise,
+            name: Name::new("sunrise"),
+            render: |ctx, state, frame| sunrise::Sunrise.render(ctx, state, frame),
+            in_cycle: false,
+        },
+    ];
+}
+
+impl Mode {
+    /// Returns the position of this mode in [`MODES`].
+    fn position(self) -> usize {
+        (0..MODES.len())
+            .position(|index| MODES.load_part(index, |info| &info.mode) == self)
+            .unwrap()
+    }
+
+    /// Returns the entry of [`MODES`] corresponding to this mode.
+    pub fn info(self) -> ModeInfo {
+        MODES.load(self.position())
+    }
+
+    /// Returns the identifier of this mode, which is stable and can be stored.
+    pub const fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the mode with the given identifier, if any.
+    pub fn from_id(id: u8) -> Option<Mode> {
+        MODES
+            .iter()
+            .find(|info| info.mode.id() == id)
+            .map(|info| info.mode)
+    }
+
+    /// Returns the mode with the given name, if any.
+    pub fn from_name(name: &[u8]) -> Option<Mode> {
+        MODES
+            .iter()
+            .find(|info| info.name.as_str().as_bytes() == name)
+            .map(|info| info.mode)
+    }
+
+    /// Returns the mode that follows this one when the push button is pressed.
+    pub fn next_in_cycle(self) -> Mode {
+        MODES
+            .iter()
+            .cycle()
+            .skip(self.position() + 1)
+            .take(MODES.len())
+            .find(|info| info.in_cycle)
+            .map_or(self, |info| info.mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycle() {
+        let mut cycle = Vec::new();
+        let mut mode = Mode::Off.next_in_cycle();
+        while !cycle.contains(&mode) {
+            cycle.push(mode);
+            mode = mode.next_in_cycle();
+        }
+        for mode in [
+            Mode::SideRotation,
+            Mode::SlowDrift,
+            Mode::Cursor,
+            Mode::Strobe,
+        ] {
+            assert!(cycle.contains(&mode), "{mode:?}");
+        }
+        let num_in_cycle = MODES.iter().filter(|info| info.in_cycle).count();
+        assert_eq!(cycle.len(), num_in_cycle);
+    }
+
+    #[test]
+    fn render_every_mode() {
+        let settings = Settings::new();
+        let audio = AudioFrame {
+            level: 200,
+            bass: 150,
+            mid: 100,
+            treble: 50,
+            beat: true,
+            flash: 255,
+        };
+        let rng = Rng::new(1);
+        for info in MODES.iter() {
+            let mut state = ModeState::None;
+            // Up to after the milliseconds of the clock have wrapped around a `u32`.
+            for millis in [0, 1, 16, 999, 60_000, 3_600_000, 1 << 32, (1 << 32) + 16] {
+                let ctx = FrameContext {
+                    clock_value: Duration::from_millis(millis),
+                    updates_wrapping_counter: millis as u8,
+                    settings: &settings,
+                    audio: &audio,
+                    rng: &rng,
+                    time_of_day: TimeOfDay::from_hms(6, 45, 0),
+                };
+                let mut frame = [Rgb::new(1, 2, 3); TOTAL_LEDS];
+                (info.render)(&ctx, &mut state, &mut frame);
+            }
+        }
+    }
+
+    #[test]
+    fn music_vu_meter() {
+        let settings = Settings::new();
+        let audio = AudioFrame {
+            level: 255,
+            ..AudioFrame::default()
+        };
+        let ctx = FrameContext {
+            clock_value: Duration::ZERO,
+            updates_wrapping_counter: 0,
+            settings: &settings,
+            audio: &audio,
+            rng: &Rng::new(1),
+            time_of_day: None,
+        };
+        let mut frame = [Rgb::BLACK; TOTAL_LEDS];
+        (Mode::Music.info().render)(&ctx, &mut ModeState::None, &mut frame);
+
+        // From green to red, as bright all along.
+        let first = frame[0];
+        let last = frame[TOTAL_LEDS - 1];
+        assert!(first.g() > 120 && first.r() < 8, "{first:?}");
+        assert!(last.r() > 120 && last.g() < 8, "{last:?}");
+        for color in frame {
+            let sum = u16::from(color.r()) + u16::from(color.g());
+            assert!((120..=130).contains(&sum) && color.b() == 0, "{color:?}");
+        }
+    }
+
+    #[test]
+    fn continuous_across_wraps() {
+        let settings = Settings::new();
+        let audio = AudioFrame::default();
+        let rng = Rng::new(1);
+        let render = |mode: Mode, millis: u64| {
+            let ctx = FrameContext {
+                clock_value: Duration::from_millis(millis),
+                updates_wrapping_counter: 0,
+                settings: &settings,
+                audio: &audio,
+                rng: &rng,
+                time_of_day: None,
+            };
+            let mut frame = [Rgb::BLACK; TOTAL_LEDS];
+            (mode.info().render)(&ctx, &mut ModeState::None, &mut frame);
+            frame
+        };
+
+        // The modes that keep a state would start over from a new one at every frame.
+        for info in MODES
+            .iter()
+            .filter(|info| !matches!(info.mode, Mode::Fire | Mode::Twinkle))
+        {
+            // When the milliseconds of the clock wrap around a `u16` or a `u32`, the frame
+            // changes as little as between any two frames 1ms apart.
+            for millis in [1 << 16, 1 << 32] {
+                let before = render(info.mode, millis - 1);
+                let after = render(info.mode, millis);
+                for (before, after) in before.iter().zip(&after) {
+                    for (before, after) in before.0.iter().zip(&after.0) {
+                        assert!(before.abs_diff(*after) <= 8, "{:?} {millis}", info.mode);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// Tables stored in the flash memory only.
+//
+// The flash memory and the RAM of the AVR are separate address spaces. Constants and statics are
+// copied from the flash memory to the RAM at startup, so that they can be read like the rest of
+// the memory, but the RAM only has 2kiB. Large tables are instead put in the `.progmem` section,
+// which stays in the flash memory, and read one byte at a time with the `lpm` instruction.
+
+use core::{
+    mem::{self, MaybeUninit},
+    str,
+};
+
+/// Value stored in the flash memory. Must only be created with [`progmem!`], and can only be read
+/// with its methods.
+#[repr(transparent)]
+pub struct ProgMem<T>(T);
+
+/// Declares a static stored in the flash memory. Its type is wrapped in a [`ProgMem`].
+macro_rules! progmem {
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $value:expr;) => {
+        $(#[$attr])*
+        #[cfg_attr(target_arch = "avr", link_section = ".progmem.data")]
+        $vis static $name: $crate::progmem::ProgMem<$ty> = $crate::progmem::ProgMem::new($value);
+    };
+}
+
+pub(crate) use progmem;
+
+impl<T> ProgMem<T> {
+    #[doc(hidden)]
+    pub const fn new(value: T) -> Self {
+        ProgMem(value)
+    }
+}
+
+impl<T: Copy> ProgMem<T> {
+    /// Reads the whole value.
+    pub fn read(&self) -> T {
+        unsafe { read(&self.0) }
+    }
+}
+
+impl<T: Copy, const N: usize> ProgMem<[T; N]> {
+    /// Returns the number of entries of the table.
+    pub const fn len(&self) -> usize {
+        N
+    }
+
+    /// Reads the entry of the table at the given index. Panics if it is out of range.
+    pub fn load(&self, index: usize) -> T {
+        // Only computes the address, without reading from it.
+        let entry: *const T = &self.0[index];
+        unsafe { read(entry) }
+    }
+
+    /// Reads the part of the entry at the given index returned by `part`, which must only select
+    /// a field of the entry, without reading the rest of the entry.
+    pub fn load_part<U: Copy>(&self, index: usize, part: impl FnOnce(&T) -> &U) -> U {
+        unsafe { read(part(&self.0[index])) }
+    }
+
+    /// Returns an iterator over the entries of the table.
+    pub fn iter(&self) -> impl Iterator<Item = T> + Clone + '_ {
+        (0..N).map(|index| self.load(index))
+    }
+}
+
+/// Maximum length of a [`Name`].
+pub const MAX_NAME_LEN: usize = 17;
+
+/// Name of an entry of a table stored in the flash memory. The name is stored in the entry,
+/// padded with zeros, as a `&str` would point to a string copied to the RAM.
+#[derive(Debug, Copy, Clone)]
+pub struct Name([u8; MAX_NAME_LEN]);
+
+impl Name {
+    /// Panics if the name is longer than [`MAX_NAME_LEN`] or contains zeros.
+    pub const fn new(name: &str) -> Self {
+        let bytes = name.as_bytes();
+        assert!(bytes.len() <= MAX_NAME_LEN, "name too long");
+        let mut out = [0; MAX_NAME_LEN];
+        let mut n = 0;
+        while n < bytes.len() {
+            assert!(bytes[n] != 0, "name containing a zero");
+            out[n] = bytes[n];
+            n += 1;
+        }
+        Name(out)
+    }
+
+    pub fn as_str(&self) -> &str {
+        let len = self
+            .0
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(MAX_NAME_LEN);
+        // The bytes before the first zero are those of the `&str` that the name was built from.
+        unsafe { str::from_utf8_unchecked(&self.0[..len]) }
+    }
+}
+
+/// Reads a value at the given address of the flash memory.
+unsafe fn read<T: Copy>(src: *const T) -> T {
+    let mut out = MaybeUninit::<T>::uninit();
+    let src = src.cast::<u8>();
+    let dest = out.as_mut_ptr().cast::<u8>();
+    for n in 0..mem::size_of::<T>() {
+        *dest.add(n) = read_byte(src.add(n));
+    }
+    out.assume_init()
+}
+
+#[cfg(target_arch = "avr")]
+unsafe fn read_byte(src: *const u8) -> u8 {
+    let out: u8;
+    core::arch::asm!(
+        "lpm {out}, Z",
+        out = out(reg) out,
+        in("Z") src,
+        options(pure, readonly, preserves_flags, nostack)
+    );
+    out
+}
+
+// Outside of the AVR, there is a single address space.
+#[cfg(not(target_arch = "avr"))]
+unsafe fn read_byte(src: *const u8) -> u8 {
+    *src
+}
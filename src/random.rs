@@ -0,0 +1,124 @@
+// Pseudo-random numbers for the effects.
+//
+// The generator is a 32 bits xorshift, which is cheap to compute on an 8 bits microcontroller
+// and more than random enough for choosing which LED sparkles next. On the device, the seed comes
+// from the noise of the ADC, so that the effects are different after each reset. A fixed seed
+// makes the effects reproducible.
+
+use core::cell::Cell;
+
+/// Pseudo-random number generator.
+///
+/// The state is in a `Cell`, so that patterns can draw numbers through the shared
+/// [`crate::pattern::FrameContext`].
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: Cell<u32>,
+}
+
+impl Rng {
+    /// Builds a generator from the given seed. Generators built with the same seed produce the
+    /// same numbers.
+    pub const fn new(seed: u32) -> Self {
+        // A state of 0 would only ever produce 0.
+        Rng {
+            state: Cell::new(if seed == 0 { 0x9e37_79b9 } else { seed }),
+        }
+    }
+
+    /// Builds a seed from samples of a noisy source, such as an unconnected analog input or the
+    /// least significant bits of a microphone.
+    pub fn seed_from_noise(samples: impl Iterator<Item = u16>) -> u32 {
+        samples.fold(0, |seed, sample| seed.rotate_left(5) ^ u32::from(sample))
+    }
+
+    pub fn random32(&self) -> u32 {
+        let mut x = self.state.get();
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state.set(x);
+        x
+    }
+
+    pub fn random16(&self) -> u16 {
+        (self.random32() >> 16) as u16
+    }
+
+    pub fn random8(&self) -> u8 {
+        (self.random32() >> 24) as u8
+    }
+
+    /// Returns a number lower than `limit`, or 0 if `limit` is 0.
+    pub fn random8_below(&self, limit: u8) -> u8 {
+        ((u16::from(self.random8()) * u16::from(limit)) >> 8) as u8
+    }
+
+    /// Returns a number between `min` included and `max` excluded. `max` must be greater than or
+    /// equal to `min`.
+    pub fn random8_between(&self, min: u8, max: u8) -> u8 {
+        debug_assert!(min <= max);
+        min + self.random8_below(max - min)
+    }
+
+    /// Returns a number lower than `limit`, or 0 if `limit` is 0.
+    pub fn random16_below(&self, limit: u16) -> u16 {
+        ((u32::from(self.random16()) * u32::from(limit)) >> 16) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden() {
+        // Computed with an independent implementation of xorshift32.
+        let rng = Rng::new(42);
+        let numbers: Vec<_> = (0..5).map(|_| rng.random32()).collect();
+        assert_eq!(
+            numbers,
+            [
+                0x00ad_4528,
+                0xa90a_34ac,
+                0x1c67_af03,
+                0xd970_c3c0,
+                0xe01c_cbc4
+            ]
+        );
+
+        let rng = Rng::new(42);
+        let numbers: Vec<_> = (0..8).map(|_| rng.random8()).collect();
+        assert_eq!(numbers, [0, 169, 28, 217, 224, 85, 221, 144]);
+
+        let rng = Rng::new(0);
+        assert_eq!(rng.random32(), 0x510c_4619);
+        assert_eq!(rng.random32(), 0xe02e_553e);
+
+        assert_eq!(
+            Rng::seed_from_noise([3, 1, 4, 1, 5, 9, 2, 6].into_iter()),
+            0x4812_a45e
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let rng = Rng::new(1);
+        let mut counts = [0u32; 10];
+        for _ in 0..10_000 {
+            counts[usize::from(rng.random8_below(10))] += 1;
+
+            let n = rng.random8_between(100, 110);
+            assert!((100..110).contains(&n), "{n}");
+            assert!(rng.random16_below(1_000) < 1_000);
+        }
+        // Roughly uniform.
+        for count in counts {
+            assert!((800..1_200).contains(&count), "{counts:?}");
+        }
+
+        assert_eq!(rng.random8_below(0), 0);
+        assert_eq!(rng.random8_between(5, 5), 5);
+        assert_eq!(rng.random16_below(0), 0);
+    }
+}
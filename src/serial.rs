@@ -0,0 +1,508 @@
+// Commands sent to the device from a computer through the serial port of the Arduino.
+//
+// Bytes are received by the interrupt handler of the UART and pushed to an `RxQueue`, then
+// assembled into lines by the main loop. Each line is a command, made of words separated with
+// spaces, and is answered with either `ok` or `error: ` followed by a message. The keys of
+// unknown infrared remotes are also reported at any time, as `ir: unknown key` followed by their
+// address and command.
+//
+// The baud rate is low on purpose: uploading the data of the LEDs disables the interrupts for
+// around 5ms, and the UART can only hold three bytes in the meantime. At 2400 bauds, a byte
+// takes more than 4ms to be received.
+
+use crate::{
+    color::Rgb,
+    frame_rate,
+    leds::Mode,
+    palette::{Palette, PALETTE_LEN},
+    params::Preset,
+    time_of_day::TimeOfDay,
+};
+
+#[cfg(target_arch = "avr")]
+pub const BAUD_RATE: u32 = 2400;
+
+/// Value of the baud rate register corresponding to [`BAUD_RATE`].
+#[cfg(target_arch = "avr")]
+pub const UBRR: u16 = ((16_000_000 + 8 * BAUD_RATE) / (16 * BAUD_RATE) - 1) as u16;
+
+/// Number of received bytes that can be waiting to be processed by the main loop.
+const RX_QUEUE_LEN: usize = 16;
+
+/// Maximum length of a line, enough for a `palette` command with 16 colors.
+pub const MAX_LINE_LEN: usize = 128;
+
+/// Bytes received by the interrupt handler and not processed yet.
+#[derive(Debug, Clone)]
+pub struct RxQueue {
+    buffer: [u8; RX_QUEUE_LEN],
+    start: u8,
+    len: u8,
+    /// `true` if bytes have been discarded because the queue was full.
+    overflowed: bool,
+}
+
+impl RxQueue {
+    pub const fn new() -> Self {
+        RxQueue {
+            buffer: [0; RX_QUEUE_LEN],
+            start: 0,
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Adds a byte at the end of the queue, or discards it if the queue is full.
+    pub fn push(&mut self, byte: u8) {
+        if usize::from(self.len) == RX_QUEUE_LEN {
+            self.overflowed = true;
+            return;
+        }
+
+        let index = (usize::from(self.start) + usize::from(self.len)) % RX_QUEUE_LEN;
+        self.buffer[index] = byte;
+        self.len += 1;
+    }
+
+    /// Removes the first byte of the queue. The second element is `true` if bytes that
+    /// followed this one have been discarded.
+    pub fn pop(&mut self) -> Option<(u8, bool)> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let byte = self.buffer[usize::from(self.start)];
+        self.start = ((usize::from(self.start) + 1) % RX_QUEUE_LEN) as u8;
+        self.len -= 1;
+
+        // Bytes are only discarded when the queue is full, in other words after the last byte
+        // of the queue.
+        let lost_after = self.overflowed && self.len == 0;
+        if lost_after {
+            self.overflowed = false;
+        }
+        Some((byte, lost_after))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Assembles the received bytes into lines.
+#[derive(Debug, Clone)]
+pub struct LineReader {
+    buffer: [u8; MAX_LINE_LEN],
+    len: usize,
+    /// `true` if bytes of the current line have been lost.
+    corrupted: bool,
+}
+
+impl LineReader {
+    pub const fn new() -> Self {
+        LineReader {
+            buffer: [0; MAX_LINE_LEN],
+            len: 0,
+            corrupted: false,
+        }
+    }
+
+    /// Feeds a received byte. Returns the line if this byte terminates it. `lost_after` must be
+    /// `true` if bytes that followed this one have been lost.
+    pub fn push(&mut self, byte: u8, lost_after: bool) -> Option<Result<&[u8], Error>> {
+        match byte {
+            b'\n' => {
+                let len = self.len;
+                let corrupted = self.corrupted;
+                self.len = 0;
+                self.corrupted = lost_after;
+                if corrupted {
+                    Some(Err(Error::LineCorrupted))
+                } else {
+                    Some(Ok(&self.buffer[..len]))
+                }
+            }
+            b'\r' => {
+                self.corrupted |= lost_after;
+                None
+            }
+            _ if self.len == MAX_LINE_LEN => {
+                self.corrupted = true;
+                None
+            }
+            _ => {
+                self.buffer[self.len] = byte;
+                self.len += 1;
+                self.corrupted |= lost_after;
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `mode <name>`: selects the mode with the given name. See [`crate::pattern::MODES`].
+    Mode(Mode),
+    /// `preset <name>`: selects the mode of the preset with the given name, with its parameters.
+    /// See [`crate::params::PRESETS`].
+    Preset(Preset),
+    /// `palette <name>` or `palette <color> ... <color>`: sets the palette of the current mode,
+    /// either to a built-in palette (see [`crate::palette::BUILT_IN`]) or to 16 colors written
+    /// as `rrggbb` in hexadecimal.
+    Palette(Palette),
+    /// `time <HH:MM[:SS]>`: sets the current time of the day.
+    Time(TimeOfDay),
+    /// `alarm <HH:MM> <minutes>` or `alarm off`: enables the sunrise alarm, with the given
+    /// wake-up time and duration of the sunrise between 10 and 60 minutes, or disables it.
+    Alarm(Option<(TimeOfDay, u8)>),
+    /// `sleep <minutes>` or `sleep off`: starts the sleep timer, switching the LEDs off after
+    /// the given number of minutes, or cancels it.
+    Sleep(Option<u8>),
+    /// `fps <n>`: sets the frame rate to target, between 1 and 100 frames per second.
+    Fps(u8),
+    /// `status`: sends back the current mode, the measured frame rate and the average time spent
+    /// rendering a frame.
+    Status,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The line is too long, or bytes have been lost while receiving it.
+    LineCorrupted,
+    UnknownCommand,
+    InvalidArgument,
+    /// The current mode doesn't use a palette. Only known by the main loop.
+    #[cfg_attr(not(target_arch = "avr"), allow(dead_code))]
+    NoPalette,
+}
+
+impl Error {
+    /// Returns the message sent back for this error.
+    #[cfg_attr(not(target_arch = "avr"), allow(dead_code))]
+    pub fn message(&self) -> &'static str {
+        match self {
+            Error::LineCorrupted => "line corrupted",
+            Error::UnknownCommand => "unknown command",
+            Error::InvalidArgument => "invalid argument",
+            Error::NoPalette => "no palette in this mode",
+        }
+    }
+}
+
+/// Parses a line received from the serial port.
+// Once inlined in the main loop, the variables used to parse would take room in the stack for
+// the whole loop, including while the frame is rendered.
+#[inline(never)]
+pub fn parse_command(line: &[u8]) -> Result<Command, Error> {
+    let mut words = line.split(|b| *b == b' ').filter(|word| !word.is_empty());
+
+    let command = match words.next() {
+        Some(b"mode") => {
+            let name = words.next().ok_or(Error::InvalidArgument)?;
+            Command::Mode(Mode::from_name(name).ok_or(Error::InvalidArgument)?)
+        }
+        Some(b"preset") => {
+            let name = words.next().ok_or(Error::InvalidArgument)?;
+            Command::Preset(Preset::from_name(name).ok_or(Error::InvalidArgument)?)
+        }
+        Some(b"palette") => {
+            let first = words.next().ok_or(Error::InvalidArgument)?;
+            if let Some(palette) = Palette::from_name(first) {
+                Command::Palette(palette)
+            } else {
+                let mut palette = Palette([Rgb::BLACK; PALETTE_LEN]);
+                let mut words = Some(first).into_iter().chain(&mut words);
+                for color in &mut palette.0 {
+                    let word = words.next().ok_or(Error::InvalidArgument)?;
+                    *color = parse_hex_color(word).ok_or(Error::InvalidArgument)?;
+                }
+                Command::Palette(palette)
+            }
+        }
+        Some(b"time") => {
+            let time = words.next().ok_or(Error::InvalidArgument)?;
+            Command::Time(TimeOfDay::parse(time).ok_or(Error::InvalidArgument)?)
+        }
+        Some(b"alarm") => match words.next().ok_or(Error::InvalidArgument)? {
+            b"off" => Command::Alarm(None),
+            time => {
+                let time = TimeOfDay::parse(time).ok_or(Error::InvalidArgument)?;
+                let duration = words
+                    .next()
+                    .and_then(parse_decimal)
+                    .filter(|minutes| (10..=60).contains(minutes))
+                    .ok_or(Error::InvalidArgument)?;
+                Command::Alarm(Some((time, duration)))
+            }
+        },
+        Some(b"sleep") => match words.next().ok_or(Error::InvalidArgument)? {
+            b"off" => Command::Sleep(None),
+            minutes => Command::Sleep(Some(
+                parse_decimal(minutes)
+                    .filter(|minutes| *minutes != 0)
+                    .ok_or(Error::InvalidArgument)?,
+            )),
+        },
+        Some(b"fps") => Command::Fps(
+            words
+                .next()
+                .and_then(parse_decimal)
+                .filter(|fps| (frame_rate::MIN_FPS..=frame_rate::MAX_FPS).contains(fps))
+                .ok_or(Error::InvalidArgument)?,
+        ),
+        Some(b"status") => Command::Status,
+        _ => return Err(Error::UnknownCommand),
+    };
+
+    if words.next().is_some() {
+        return Err(Error::InvalidArgument);
+    }
+
+    Ok(command)
+}
+
+/// Parses a color written as `rrggbb` in hexadecimal.
+fn parse_hex_color(word: &[u8]) -> Option<Rgb> {
+    if word.len() != 6 {
+        return None;
+    }
+
+    let mut color = Rgb::BLACK;
+    for (component, digits) in color.0.iter_mut().zip(word.chunks(2)) {
+        *component = digits.iter().try_fold(0, |component, digit| {
+            Some(component << 4 | char::from(*digit).to_digit(16)? as u8)
+        })?;
+    }
+    Some(color)
+}
+
+/// Parses a number written in decimal.
+///
+/// Unlike `str::parse`, this doesn't need the table of the lengths of UTF-8 characters of the
+/// core library, which would take 256 bytes of RAM.
+fn parse_decimal(word: &[u8]) -> Option<u8> {
+    if word.is_empty() {
+        return None;
+    }
+
+    word.iter().try_fold(0u8, |value, digit| {
+        value
+            .checked_mul(10)?
+            .checked_add(char::from(*digit).to_digit(10)? as u8)
+    })
+}
+
+/// Writes `value` in decimal at the end of `buffer`, and returns the digits.
+pub fn format_decimal(mut value: u32, buffer: &mut [u8; 10]) -> &[u8] {
+    let mut start = buffer.len();
+    loop {
+        start -= 1;
+        buffer[start] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            return &buffer[start..];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::{FireParams, Preset};
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse_command(b"mode fire"), Ok(Command::Mode(Mode::Fire)));
+        assert_eq!(
+            parse_command(b"  mode  fire "),
+            Ok(Command::Mode(Mode::Fire))
+        );
+        assert_eq!(
+            parse_command(b"preset embers"),
+            Ok(Command::Preset(Preset::Fire(FireParams::EMBERS)))
+        );
+        assert_eq!(
+            parse_command(b"palette lava"),
+            Ok(Command::Palette(Palette::LAVA))
+        );
+        assert_eq!(
+            parse_command(b"time 23:59:59"),
+            Ok(Command::Time(TimeOfDay::from_hms(23, 59, 59).unwrap()))
+        );
+        assert_eq!(
+            parse_command(b"alarm 07:30 10"),
+            Ok(Command::Alarm(Some((
+                TimeOfDay::from_hms(7, 30, 0).unwrap(),
+                10
+            ))))
+        );
+        assert_eq!(parse_command(b"alarm off"), Ok(Command::Alarm(None)));
+        assert_eq!(parse_command(b"sleep 255"), Ok(Command::Sleep(Some(255))));
+        assert_eq!(parse_command(b"sleep off"), Ok(Command::Sleep(None)));
+        assert_eq!(parse_command(b"fps 1"), Ok(Command::Fps(1)));
+        assert_eq!(parse_command(b"fps 100"), Ok(Command::Fps(100)));
+        assert_eq!(parse_command(b"status"), Ok(Command::Status));
+    }
+
+    #[test]
+    fn custom_palette() {
+        let colors: Vec<_> = (0..PALETTE_LEN)
+            .map(|n| format!("{:02x}80ff", n * 16))
+            .collect();
+        let line = format!("palette {}", colors.join(" "));
+        assert!(line.len() <= MAX_LINE_LEN);
+        let Ok(Command::Palette(palette)) = parse_command(line.as_bytes()) else {
+            panic!("{line}");
+        };
+        for (n, color) in palette.0.iter().enumerate() {
+            assert_eq!(*color, Rgb::new(n as u8 * 16, 0x80, 0xff));
+        }
+
+        // Exactly 16 colors.
+        let line = format!("palette {}", colors[..15].join(" "));
+        assert_eq!(parse_command(line.as_bytes()), Err(Error::InvalidArgument));
+        let line = format!("palette {} 000000", colors.join(" "));
+        assert_eq!(parse_command(line.as_bytes()), Err(Error::InvalidArgument));
+    }
+
+    #[test]
+    fn malformed_commands() {
+        for line in [&b""[..], b" ", b"foo", b"MODE fire", b"modefire"] {
+            assert_eq!(parse_command(line), Err(Error::UnknownCommand), "{line:?}");
+        }
+        for line in [
+            &b"mode"[..],
+            b"mode unknown",
+            b"mode fire fire",
+            b"preset",
+            b"preset ember",
+            b"palette",
+            b"palette unknown",
+            b"time",
+            b"time 7",
+            b"time 24:00",
+            b"time 12:60",
+            b"time 12:00:60",
+            b"alarm",
+            b"alarm 07:30",
+            b"alarm 07:30 9",
+            b"alarm 07:30 61",
+            b"alarm 07:30 300",
+            b"alarm 24:00 30",
+            b"alarm off 30",
+            b"sleep",
+            b"sleep 0",
+            b"sleep 256",
+            b"sleep -1",
+            b"fps",
+            b"fps 0",
+            b"fps 101",
+            b"fps 1000",
+            b"status now",
+        ] {
+            assert_eq!(parse_command(line), Err(Error::InvalidArgument), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(parse_hex_color(b"ff8000"), Some(Rgb::new(255, 128, 0)));
+        assert_eq!(parse_hex_color(b"0A0b0C"), Some(Rgb::new(10, 11, 12)));
+        for word in [
+            &b""[..],
+            b"ff800",
+            b"ff80000",
+            b"ff800g",
+            b"+f8000",
+            b"ff 800",
+        ] {
+            assert_eq!(parse_hex_color(word), None, "{word:?}");
+        }
+    }
+
+    #[test]
+    fn decimals() {
+        assert_eq!(parse_decimal(b"0"), Some(0));
+        assert_eq!(parse_decimal(b"007"), Some(7));
+        assert_eq!(parse_decimal(b"255"), Some(255));
+        for word in [&b""[..], b"256", b"1000", b"-1", b"+1", b"1a", b" 1"] {
+            assert_eq!(parse_decimal(word), None, "{word:?}");
+        }
+
+        let mut buffer = [0; 10];
+        assert_eq!(format_decimal(0, &mut buffer), b"0");
+        assert_eq!(format_decimal(1234, &mut buffer), b"1234");
+        assert_eq!(format_decimal(u32::MAX, &mut buffer), b"4294967295");
+    }
+
+    /// Feeds the given bytes to a line reader, and returns the lines it produced.
+    fn read_lines(reader: &mut LineReader, bytes: &[u8]) -> Vec<Result<Vec<u8>, Error>> {
+        bytes
+            .iter()
+            .filter_map(|byte| {
+                reader
+                    .push(*byte, false)
+                    .map(|line| line.map(<[u8]>::to_vec))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lines() {
+        let mut reader = LineReader::new();
+        assert_eq!(
+            read_lines(&mut reader, b"mode fire\r\nstatus\n\nfps"),
+            [
+                Ok(b"mode fire".to_vec()),
+                Ok(b"status".to_vec()),
+                Ok(vec![])
+            ]
+        );
+        assert_eq!(read_lines(&mut reader, b" 30\n"), [Ok(b"fps 30".to_vec())]);
+    }
+
+    #[test]
+    fn oversized_line() {
+        let mut reader = LineReader::new();
+        let mut bytes = vec![b'a'; MAX_LINE_LEN];
+        bytes.push(b'\n');
+        assert_eq!(
+            read_lines(&mut reader, &bytes),
+            [Ok(vec![b'a'; MAX_LINE_LEN])]
+        );
+
+        bytes.insert(0, b'a');
+        bytes.extend(b"status\n");
+        assert_eq!(
+            read_lines(&mut reader, &bytes),
+            [Err(Error::LineCorrupted), Ok(b"status".to_vec())]
+        );
+    }
+
+    #[test]
+    fn lost_bytes() {
+        let mut queue = RxQueue::new();
+        let mut reader = LineReader::new();
+        // The main loop was too slow to empty the queue, which overflowed and lost the end of
+        // the second line.
+        for byte in b"mode fire\nstatus\n" {
+            queue.push(*byte);
+        }
+        assert!(!queue.is_empty());
+        let mut lines = Vec::new();
+        while let Some((byte, lost_after)) = queue.pop() {
+            if let Some(line) = reader.push(byte, lost_after) {
+                lines.push(line.map(<[u8]>::to_vec));
+            }
+        }
+        assert_eq!(lines, [Ok(b"mode fire".to_vec())]);
+        assert!(queue.is_empty());
+
+        // The line is reported once it ends, and the next one is fine.
+        assert_eq!(
+            read_lines(&mut reader, b"\nstatus\n"),
+            [Err(Error::LineCorrupted), Ok(b"status".to_vec())]
+        );
+    }
+}
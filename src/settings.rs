@@ -0,0 +1,247 @@
+use crate::{
+    leds::Mode,
+    palette::{Palette, PALETTE_LEN},
+    params::{
+        AlternatingColorParams, FireParams, FireplaceParams, MeteorParams, Preset, RainbowParams,
+        SegmentLightsParams, SunriseParams, TwinkleParams,
+    },
+    progmem::progmem,
+};
+
+/// Parameters of the effects that can be adjusted at runtime through the inputs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// Global brightness, applied to all the LEDs.
+    pub brightness: u8,
+    pub fireplace: FireplaceParams,
+    pub segment_lights: SegmentLightsParams,
+    pub alternating_color: AlternatingColorParams,
+    /// See [`Settings::speed`].
+    pub party_cycle_speed: u8,
+    /// See [`Settings::speed`].
+    pub side_rotation_speed: u8,
+    /// See [`Settings::speed`].
+    pub slow_drift_speed: u8,
+    /// See [`Settings::speed`].
+    pub cursor_speed: u8,
+    /// See [`Settings::speed`].
+    pub strobe_speed: u8,
+    pub rainbow: RainbowParams,
+    pub rainbow_glitter: RainbowParams,
+    pub theatre_chase: RainbowParams,
+    pub hue_cycle: RainbowParams,
+    pub fire: FireParams,
+    pub twinkle: TwinkleParams,
+    pub meteor: MeteorParams,
+    pub sunrise: SunriseParams,
+}
+
+impl Settings {
+    pub const fn new() -> Self {
+        Settings {
+            brightness: 255,
+            fireplace: FireplaceParams::ORANGE,
+            segment_lights: SegmentLightsParams::CLASSIC,
+            alternating_color: AlternatingColorParams::CLASSIC,
+            party_cycle_speed: NORMAL_SPEED,
+            side_rotation_speed: NORMAL_SPEED,
+            slow_drift_speed: NORMAL_SPEED,
+            cursor_speed: NORMAL_SPEED,
+            strobe_speed: NORMAL_SPEED,
+            rainbow: RainbowParams::VIVID,
+            rainbow_glitter: RainbowParams::VIVID,
+            theatre_chase: RainbowParams::VIVID,
+            hue_cycle: RainbowParams::PASTEL,
+            fire: FireParams::CLASSIC,
+            twinkle: TwinkleParams::STARS,
+            meteor: MeteorParams::WHITE,
+            sunrise: SunriseParams::SEVEN_AM,
+        }
+    }
+
+    /// Returns the speed multiplier of the effects of the given mode. [`NORMAL_SPEED`] is the
+    /// normal speed, 0 freezes the effects, and 255 makes them run almost four times as fast.
+    pub fn speed(&self, mode: Mode) -> u8 {
+        match mode {
+            Mode::Fireplace => self.fireplace.speed,
+            Mode::SegmentLights => self.segment_lights.speed,
+            Mode::WholeStripAlternatingColor => self.alternating_color.speed,
+            Mode::PartyCycle => self.party_cycle_speed,
+            Mode::SideRotation => self.side_rotation_speed,
+            Mode::SlowDrift => self.slow_drift_speed,
+            Mode::Cursor => self.cursor_speed,
+            Mode::Strobe => self.strobe_speed,
+            Mode::Rainbow => self.rainbow.speed,
+            Mode::RainbowGlitter => self.rainbow_glitter.speed,
+            Mode::TheatreChase => self.theatre_chase.speed,
+            Mode::HueCycle => self.hue_cycle.speed,
+            Mode::Fire => self.fire.speed,
+            Mode::Twinkle => self.twinkle.speed,
+            Mode::Meteor => self.meteor.speed,
+            Mode::Off | Mode::Neutral | Mode::Music | Mode::Sunrise => NORMAL_SPEED,
+        }
+    }
+
+    /// Sets the speed multiplier of the given mode. Has no effect for modes that aren't
+    /// animated.
+    pub fn set_speed(&mut self, mode: Mode, speed: u8) {
+        match mode {
+            Mode::Fireplace => self.fireplace.speed = speed,
+            Mode::SegmentLights => self.segment_lights.speed = speed,
+            Mode::WholeStripAlternatingColor => self.alternating_color.speed = speed,
+            Mode::PartyCycle => self.party_cycle_speed = speed,
+            Mode::SideRotation => self.side_rotation_speed = speed,
+            Mode::SlowDrift => self.slow_drift_speed = speed,
+            Mode::Cursor => self.cursor_speed = speed,
+            Mode::Strobe => self.strobe_speed = speed,
+            Mode::Rainbow => self.rainbow.speed = speed,
+            Mode::RainbowGlitter => self.rainbow_glitter.speed = speed,
+            Mode::TheatreChase => self.theatre_chase.speed = speed,
+            Mode::HueCycle => self.hue_cycle.speed = speed,
+            Mode::Fire => self.fire.speed = speed,
+            Mode::Twinkle => self.twinkle.speed = speed,
+            Mode::Meteor => self.meteor.speed = speed,
+            Mode::Off | Mode::Neutral | Mode::Music | Mode::Sunrise => {}
+        }
+    }
+
+    /// Sets the palette used by the given mode, which then uses all its entries. Returns `false`
+    /// if the mode doesn't use a palette.
+    pub fn set_palette(&mut self, mode: Mode, palette: Palette) -> bool {
+        match mode {
+            Mode::SegmentLights => {
+                self.segment_lights.palette = palette;
+                self.segment_lights.num_colors = PALETTE_LEN as u8;
+            }
+            Mode::WholeStripAlternatingColor => {
+                self.alternating_color.palette = palette;
+                self.alternating_color.num_colors = PALETTE_LEN as u8;
+            }
+            Mode::Fire => self.fire.palette = palette,
+            Mode::Twinkle => self.twinkle.palette = palette,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Sets the parameters of the mode of the given preset, and returns that mode.
+    pub fn apply_preset(&mut self, preset: Preset) -> Mode {
+        match preset {
+            Preset::Fireplace(params) => {
+                self.fireplace = params;
+                Mode::Fireplace
+            }
+            Preset::Fire(params) => {
+                self.fire = params;
+                Mode::Fire
+            }
+            Preset::Twinkle(params) => {
+                self.twinkle = params;
+                Mode::Twinkle
+            }
+            Preset::Meteor(params) => {
+                self.meteor = params;
+                Mode::Meteor
+            }
+        }
+    }
+
+    /// Returns the parameters of the given mode if it is one of the rainbow modes.
+    pub fn rainbow_params_mut(&mut self, mode: Mode) -> Option<&mut RainbowParams> {
+        match mode {
+            Mode::Rainbow => Some(&mut self.rainbow),
+            Mode::RainbowGlitter => Some(&mut self.rainbow_glitter),
+            Mode::TheatreChase => Some(&mut self.theatre_chase),
+            Mode::HueCycle => Some(&mut self.hue_cycle),
+            _ => None,
+        }
+    }
+}
+
+progmem! {
+    /// Settings at startup. Kept in the flash memory, as the presets they are copied from would
+    /// otherwise take room in the RAM.
+    pub static DEFAULT: Settings = Settings::new();
+}
+
+/// Lowest global brightness that can be set, so that the LEDs can't look switched off while a mode
+/// is displayed.
+pub const MIN_BRIGHTNESS: u8 = 15;
+
+/// Value of the speed multiplier corresponding to the normal speed of an effect.
+pub const NORMAL_SPEED: u8 = 64;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::MODES;
+
+    #[test]
+    fn speeds() {
+        for info in MODES.iter() {
+            let mut settings = Settings::new();
+            settings.set_speed(info.mode, 1);
+            let animated = !matches!(
+                info.mode,
+                Mode::Off | Mode::Neutral | Mode::Music | Mode::Sunrise
+            );
+            let expected = if animated { 1 } else { NORMAL_SPEED };
+            assert_eq!(settings.speed(info.mode), expected, "{:?}", info.mode);
+        }
+    }
+
+    #[test]
+    fn palettes() {
+        let mut settings = Settings::new();
+        for mode in [
+            Mode::SegmentLights,
+            Mode::WholeStripAlternatingColor,
+            Mode::Fire,
+            Mode::Twinkle,
+        ] {
+            assert!(settings.set_palette(mode, Palette::FOREST), "{mode:?}");
+        }
+        assert_eq!(settings.segment_lights.palette, Palette::FOREST);
+        assert_eq!(usize::from(settings.segment_lights.num_colors), PALETTE_LEN);
+        assert_eq!(settings.fire.palette, Palette::FOREST);
+
+        let before = settings;
+        assert!(!settings.set_palette(Mode::Rainbow, Palette::OCEAN));
+        assert!(!settings.set_palette(Mode::Off, Palette::OCEAN));
+        assert_eq!(settings, before);
+    }
+
+    #[test]
+    fn presets() {
+        let mut settings = Settings::new();
+        assert_eq!(
+            settings.apply_preset(Preset::Fire(FireParams::EMBERS)),
+            Mode::Fire
+        );
+        assert_eq!(settings.fire, FireParams::EMBERS);
+        assert_eq!(
+            settings.apply_preset(Preset::Fireplace(FireplaceParams::BLUE)),
+            Mode::Fireplace
+        );
+        assert_eq!(settings.fireplace, FireplaceParams::BLUE);
+    }
+
+    #[test]
+    fn rainbow_params() {
+        let mut settings = Settings::new();
+        for mode in [
+            Mode::Rainbow,
+            Mode::RainbowGlitter,
+            Mode::TheatreChase,
+            Mode::HueCycle,
+        ] {
+            assert!(settings.rainbow_params_mut(mode).is_some(), "{mode:?}");
+        }
+        assert!(settings.rainbow_params_mut(Mode::Fire).is_none());
+    }
+
+    #[test]
+    fn default_settings() {
+        assert_eq!(DEFAULT.read(), Settings::new());
+    }
+}
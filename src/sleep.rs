@@ -0,0 +1,132 @@
+// Sleep timer, switching the LEDs off after some time.
+//
+// The brightness slowly fades out during the last minutes, so that the moment the LEDs switch
+// off isn't noticeable, then the mode is set to off.
+
+use core::{cmp, time::Duration};
+
+use crate::leds::Mode;
+
+/// Duration during which the brightness fades out before the end of the timer.
+const FADE_DURATION: Duration = Duration::from_secs(5 * 60);
+
+/// Duration of the timer started with the push button.
+pub const BUTTON_DURATION: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SleepTimer {
+    /// Value of the clock at which the LEDs must be switched off.
+    end: Duration,
+    /// Duration of the fade out. Shorter than [`FADE_DURATION`] if the timer is.
+    fade_duration: Duration,
+}
+
+impl SleepTimer {
+    /// Starts a timer of the given duration, given the current value of the clock.
+    pub fn new(clock_value: Duration, duration: Duration) -> Self {
+        SleepTimer {
+            end: clock_value + duration,
+            fade_duration: cmp::min(duration, FADE_DURATION),
+        }
+    }
+
+    /// Returns the factor to apply to the brightness at the given value of the clock, where
+    /// 255 is 1, or `None` if the timer has expired.
+    pub fn brightness_scale(&self, clock_value: Duration) -> Option<u8> {
+        let remaining = self.end.checked_sub(clock_value)?;
+        if remaining.is_zero() {
+            return None;
+        }
+
+        if remaining >= self.fade_duration {
+            Some(255)
+        } else {
+            // Both durations are shorter than `FADE_DURATION`, so they fit in a `u32` of
+            // milliseconds, which is much cheaper to divide than the `u128` of `as_millis`.
+            let millis =
+                |duration: Duration| duration.as_secs() as u32 * 1000 + duration.subsec_millis();
+            Some((millis(remaining) * 255 / millis(self.fade_duration)) as u8)
+        }
+    }
+}
+
+/// Applies the sleep timer, if any, at the given value of the clock. Switches the mode off once
+/// the timer has expired, and cancels the timer if the mode is off. Returns the factor to apply
+/// to the brightness, where 255 is 1.
+pub fn update(timer: &mut Option<SleepTimer>, mode: &mut Mode, clock_value: Duration) -> u8 {
+    if *mode == Mode::Off {
+        *timer = None;
+    }
+    match timer.map(|timer| timer.brightness_scale(clock_value)) {
+        Some(Some(scale)) => scale,
+        Some(None) => {
+            *mode = Mode::Off;
+            *timer = None;
+            255
+        }
+        None => 255,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a timer of the given duration started at `start` with a clock ticking every second,
+    /// and returns the brightness factor at each tick until the mode has been switched off.
+    fn run(start: Duration, duration: Duration) -> Vec<u8> {
+        let mut timer = Some(SleepTimer::new(start, duration));
+        let mut mode = Mode::Fireplace;
+        let mut scales = Vec::new();
+        let mut clock_value = start;
+        while mode != Mode::Off {
+            scales.push(update(&mut timer, &mut mode, clock_value));
+            clock_value += Duration::from_secs(1);
+        }
+        assert_eq!(timer, None);
+        scales
+    }
+
+    #[test]
+    fn fades_out_then_switches_off() {
+        for start in [Duration::ZERO, Duration::from_secs(10 * 365 * 24 * 3600)] {
+            let scales = run(start, BUTTON_DURATION);
+            // Switched off when the timer expires.
+            assert_eq!(scales.len(), 30 * 60 + 1);
+            // Full brightness until the last 5 minutes.
+            assert!(scales[..25 * 60 + 1].iter().all(|scale| *scale == 255));
+            // Then fading out steadily down to black.
+            for (n, scale) in scales[25 * 60..30 * 60].iter().enumerate() {
+                let expected = 255.0 * (300 - n) as f64 / 300.0;
+                assert!((f64::from(*scale) - expected).abs() <= 1.0, "{n} {scale}");
+            }
+            assert_eq!(scales[27 * 60 + 30], 127);
+            assert_eq!(scales[30 * 60 - 1], 0);
+            // Back to full brightness with the mode off, as the LEDs are off anyway.
+            assert_eq!(scales[30 * 60], 255);
+        }
+    }
+
+    #[test]
+    fn short_timer() {
+        // The fade out lasts as long as the timer.
+        let scales = run(Duration::from_secs(100), Duration::from_secs(60));
+        assert_eq!(scales.len(), 61);
+        assert_eq!(scales[0], 255);
+        assert_eq!(scales[30], 127);
+        assert_eq!(scales[59], 4);
+    }
+
+    #[test]
+    fn cancelled_when_off() {
+        let mut timer = Some(SleepTimer::new(Duration::ZERO, BUTTON_DURATION));
+        let mut mode = Mode::Off;
+        assert_eq!(update(&mut timer, &mut mode, Duration::from_secs(1)), 255);
+        assert_eq!(timer, None);
+
+        // Without a timer, nothing changes.
+        let mut mode = Mode::Fireplace;
+        assert_eq!(update(&mut timer, &mut mode, FADE_DURATION * 100), 255);
+        assert_eq!(mode, Mode::Fireplace);
+    }
+}
@@ -0,0 +1,51 @@
+// Sunrise alarm, slowly brightening the room from a dim deep red to a bright warm white, ending
+// at the wake-up time.
+
+use crate::{
+    color::Rgb,
+    params::SunriseParams,
+    pattern::{Frame, FrameContext, ModeState, Pattern},
+    progmem::progmem,
+    time_of_day::TimeOfDay,
+};
+
+include!(concat!(env!("OUT_DIR"), "/sunrise_table.rs"));
+
+pub struct Sunrise;
+
+impl Pattern for Sunrise {
+    fn render(&self, ctx: &FrameContext, _: &mut ModeState, frame: &mut Frame) {
+        // If the time of the day is unknown, we can't know where we are in the sunrise, and
+        // show its end.
+        let progress = ctx
+            .time_of_day
+            .map_or(255, |now| progress(&ctx.settings.sunrise, now));
+
+        let entry = usize::from(progress >> 2);
+        let next = (entry + 1).min(SUNRISE_TABLE.len() - 1);
+        let color =
+            Rgb(SUNRISE_TABLE.load(entry)).lerp(Rgb(SUNRISE_TABLE.load(next)), (progress & 3) << 6);
+        frame.fill(color);
+    }
+}
+
+/// Returns `true` if the given time of the day is during the sunrise.
+pub fn is_rising(params: &SunriseParams, now: TimeOfDay) -> bool {
+    let seconds_left = now.seconds_until(params.wake_up_time);
+    seconds_left != 0 && seconds_left <= duration_seconds(params)
+}
+
+/// Returns how far we are in the sunrise at the given time of the day, where 255 is the end.
+/// Once the wake-up time has passed, the sunrise stays at its end.
+fn progress(params: &SunriseParams, now: TimeOfDay) -> u8 {
+    if !is_rising(params, now) {
+        return 255;
+    }
+
+    let seconds_left = now.seconds_until(params.wake_up_time);
+    (255 - seconds_left * 255 / duration_seconds(params)) as u8
+}
+
+fn duration_seconds(params: &SunriseParams) -> u32 {
+    u32::from(params.duration_minutes) * 60
+}
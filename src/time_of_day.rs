@@ -0,0 +1,138 @@
+// Time of the day, for the alarm.
+//
+// The Arduino has no battery-backed clock, and the I²C pins are used by the microphone, so the
+// time of the day is sent through the serial port and then kept by counting the overflows of
+// timer0. The ceramic resonator of the Arduino is only precise to about 0.5%, so the time can
+// drift by several minutes per day, and should be sent again regularly, for example by a cron
+// job.
+
+use core::time::Duration;
+
+const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+
+/// Number of seconds since midnight.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay(u32);
+
+impl TimeOfDay {
+    /// Builds a time from hours, minutes and seconds. Returns `None` if a value is out of range.
+    pub const fn from_hms(hours: u8, minutes: u8, seconds: u8) -> Option<Self> {
+        if hours >= 24 || minutes >= 60 || seconds >= 60 {
+            return None;
+        }
+
+        Some(TimeOfDay(
+            hours as u32 * 3600 + minutes as u32 * 60 + seconds as u32,
+        ))
+    }
+
+    /// Parses a time written as `HH:MM` or `HH:MM:SS`.
+    pub fn parse(text: &[u8]) -> Option<Self> {
+        let mut fields = text.split(|b| *b == b':').map(|field| {
+            if field.is_empty() || field.len() > 2 {
+                return None;
+            }
+            field.iter().try_fold(0u8, |value, digit| {
+                Some(value * 10 + char::from(*digit).to_digit(10)? as u8)
+            })
+        });
+
+        let hours = fields.next()??;
+        let minutes = fields.next()??;
+        let seconds = fields.next().unwrap_or(Some(0))?;
+        if fields.next().is_some() {
+            return None;
+        }
+
+        TimeOfDay::from_hms(hours, minutes, seconds)
+    }
+
+    /// Returns the number of seconds from `self` until `later`, going past midnight if `later`
+    /// is earlier in the day.
+    pub fn seconds_until(self, later: TimeOfDay) -> u32 {
+        (later.0 + SECONDS_PER_DAY - self.0) % SECONDS_PER_DAY
+    }
+}
+
+/// Derives the time of the day from the clock of the device.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WallClock {
+    /// Time of the day when the clock of the device was at 0, in seconds since midnight.
+    offset: u32,
+}
+
+impl WallClock {
+    /// Builds a `WallClock` given the current value of the clock of the device and the current
+    /// time of the day.
+    pub fn new(clock_value: Duration, now: TimeOfDay) -> Self {
+        let elapsed = (clock_value.as_secs() % u64::from(SECONDS_PER_DAY)) as u32;
+        WallClock {
+            offset: (now.0 + SECONDS_PER_DAY - elapsed) % SECONDS_PER_DAY,
+        }
+    }
+
+    /// Returns the time of the day corresponding to the given value of the clock of the device.
+    pub fn time_of_day(&self, clock_value: Duration) -> TimeOfDay {
+        let elapsed = (clock_value.as_secs() % u64::from(SECONDS_PER_DAY)) as u32;
+        TimeOfDay((self.offset + elapsed) % SECONDS_PER_DAY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hms(hours: u8, minutes: u8, seconds: u8) -> TimeOfDay {
+        TimeOfDay::from_hms(hours, minutes, seconds).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(TimeOfDay::parse(b"07:30"), Some(hms(7, 30, 0)));
+        assert_eq!(TimeOfDay::parse(b"7:05:09"), Some(hms(7, 5, 9)));
+        assert_eq!(TimeOfDay::parse(b"00:00:00"), Some(hms(0, 0, 0)));
+        assert_eq!(TimeOfDay::parse(b"23:59:59"), Some(hms(23, 59, 59)));
+        for text in [
+            &b""[..],
+            b"12",
+            b"12:",
+            b":30",
+            b"12::30",
+            b"24:00",
+            b"12:60",
+            b"12:00:60",
+            b"99:99",
+            b"123:00",
+            b"12:000",
+            b"12:00:00:00",
+            b"1a:00",
+            b"-1:00",
+            b"12:00 ",
+        ] {
+            assert_eq!(TimeOfDay::parse(text), None, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn seconds_until() {
+        assert_eq!(hms(7, 0, 0).seconds_until(hms(7, 30, 0)), 30 * 60);
+        assert_eq!(hms(7, 0, 0).seconds_until(hms(7, 0, 0)), 0);
+        // Past midnight.
+        assert_eq!(hms(23, 0, 0).seconds_until(hms(1, 0, 0)), 2 * 3600);
+    }
+
+    #[test]
+    fn wall_clock() {
+        let start = Duration::from_secs(1_000_000);
+        let wall_clock = WallClock::new(start, hms(23, 59, 0));
+        assert_eq!(wall_clock.time_of_day(start), hms(23, 59, 0));
+        assert_eq!(
+            wall_clock.time_of_day(start + Duration::from_secs(90)),
+            hms(0, 0, 30)
+        );
+        assert_eq!(
+            wall_clock.time_of_day(start + Duration::from_secs(10 * 24 * 3600 + 60)),
+            hms(0, 0, 0)
+        );
+    }
+}
@@ -0,0 +1,139 @@
+// LEDs randomly brightening and fading over a dim base color, like stars or fairy lights.
+//
+// Each LED has a phase, which is 0 while the LED isn't twinkling. At each step of the animation,
+// a new twinkle might start on a random LED, and the phase of the twinkling LEDs increases. The
+// brightness of an LED rises during the first half of its phase and falls during the second
+// half.
+
+use core::cmp;
+
+use crate::{
+    leds::TOTAL_LEDS,
+    pattern::{Frame, FrameContext, ModeState, Pattern, Stepper},
+};
+
+/// State of [`crate::leds::Mode::Twinkle`].
+#[derive(Debug, Clone)]
+pub struct TwinkleState {
+    /// Phase of each LED, in layout order.
+    phases: [u8; TOTAL_LEDS],
+    stepper: Stepper,
+}
+
+impl TwinkleState {
+    pub const fn new() -> Self {
+        TwinkleState {
+            phases: [0; TOTAL_LEDS],
+            stepper: Stepper::new(),
+        }
+    }
+}
+
+pub struct Twinkle;
+
+impl Pattern for Twinkle {
+    fn render(&self, ctx: &FrameContext, state: &mut ModeState, frame: &mut Frame) {
+        let params = &ctx.settings.twinkle;
+        let state = state.twinkle();
+        // With a speed of 0, the twinkling LEDs would stay lit forever.
+        let fade_speed = cmp::max(params.fade_speed, 1);
+
+        for _ in 0..state.stepper.advance(ctx.clock_value) {
+            for phase in state.phases.iter_mut().filter(|phase| **phase != 0) {
+                *phase = phase.saturating_add(fade_speed);
+                if *phase == u8::MAX {
+                    *phase = 0;
+                }
+            }
+
+            if ctx.rng.random8() < params.density {
+                let led_pos = usize::from(ctx.rng.random16_below(TOTAL_LEDS as u16));
+                if state.phases[led_pos] == 0 {
+                    state.phases[led_pos] = 1;
+                }
+            }
+        }
+
+        for (led_pos, (color, &phase)) in frame.iter_mut().zip(&state.phases).enumerate() {
+            let brightness = if phase < 128 {
+                phase * 2
+            } else {
+                (255 - phase) * 2
+            };
+            // Multiplying by a large odd number spreads the colors of neighbouring LEDs over the
+            // palette.
+            let twinkle_color = params.palette.get((led_pos as u8).wrapping_mul(97));
+            *color = params.base_color.lerp(twinkle_color, brightness);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::*;
+    use crate::{audio::AudioFrame, color::Rgb, random::Rng, settings::Settings};
+
+    /// Renders 100 frames 16ms apart, with the default settings, and returns the state.
+    fn render_frames(seed: u32) -> (TwinkleState, Frame) {
+        let settings = Settings::new();
+        let rng = Rng::new(seed);
+        let mut state = ModeState::None;
+        let mut frame = [Rgb::BLACK; TOTAL_LEDS];
+        for n in 0..100 {
+            let ctx = FrameContext {
+                clock_value: Duration::from_millis(16 * n),
+                updates_wrapping_counter: n as u8,
+                settings: &settings,
+                audio: &AudioFrame::default(),
+                rng: &rng,
+                time_of_day: None,
+            };
+            Twinkle.render(&ctx, &mut state, &mut frame);
+        }
+        (state.twinkle().clone(), frame)
+    }
+
+    #[test]
+    fn golden() {
+        let (state, frame) = render_frames(1);
+        let twinkling: Vec<_> = (0..TOTAL_LEDS)
+            .filter(|led_pos| state.phases[*led_pos] != 0)
+            .map(|led_pos| (led_pos, state.phases[led_pos]))
+            .collect();
+        assert_eq!(
+            twinkling,
+            [
+                (17, 221),
+                (22, 141),
+                (29, 233),
+                (30, 9),
+                (37, 217),
+                (76, 133),
+                (105, 89),
+                (117, 213),
+                (118, 29),
+                (134, 225),
+                (146, 121),
+                (149, 93),
+                (170, 129)
+            ]
+        );
+
+        let base_color = Settings::new().twinkle.base_color;
+        for (led_pos, color) in frame.iter().enumerate() {
+            assert_eq!(
+                *color == base_color,
+                !twinkling.iter().any(|(pos, _)| *pos == led_pos),
+                "{led_pos}"
+            );
+        }
+    }
+
+    #[test]
+    fn reproducible() {
+        assert_eq!(render_frames(7).1, render_frames(7).1);
+        assert_ne!(render_frames(7).1, render_frames(8).1);
+    }
+}
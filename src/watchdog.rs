@@ -0,0 +1,181 @@
+// Recovery from crashes with the watchdog timer.
+//
+// The main loop feeds the watchdog at every frame. If it stops doing so, for example because it
+// is stuck in an infinite loop, the watchdog interrupt is triggered first, and records the mode
+// being displayed in a part of the memory that isn't initialized at startup. The next timeout of
+// the watchdog resets the MCU. After the reset, the firmware can tell from the record that it
+// crashed, and starts in a safe mode instead of leaving the room dark or crashing again.
+//
+// The panic handler leaves a record in the same way, before letting the watchdog reset the MCU.
+
+use crate::leds::Mode;
+
+/// Mode selected at startup after a crash. It is the simplest one, and the least likely to crash
+/// again.
+pub const SAFE_MODE: Mode = Mode::Neutral;
+
+/// Flags of the `MCUSR` register, telling what caused the last reset.
+const POWER_ON_RESET: u8 = 1 << 0;
+const BROWN_OUT_RESET: u8 = 1 << 2;
+const WATCHDOG_RESET: u8 = 1 << 3;
+
+/// Value of [`CrashRecord::magic`] when a crash has been recorded. Any other value, including
+/// whatever the memory contains after powering on, means there was no crash.
+const MAGIC: u32 = 0x5afe_b007;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Crash {
+    /// The main loop stopped feeding the watchdog.
+    Watchdog,
+    Panic,
+}
+
+impl Crash {
+    /// Returns the message sent through the serial port after the reset.
+    #[cfg_attr(not(target_arch = "avr"), allow(dead_code))]
+    pub fn message(&self) -> &'static str {
+        match self {
+            Crash::Watchdog => "reset by the watchdog",
+            Crash::Panic => "reset after a panic",
+        }
+    }
+}
+
+/// Record of a crash, kept in memory through the reset that follows it.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct CrashRecord {
+    magic: u32,
+    /// `true` for [`Crash::Panic`]. Stored as an integer, because the memory may contain any
+    /// value.
+    panicked: u8,
+    mode_id: u8,
+}
+
+impl CrashRecord {
+    pub const NONE: CrashRecord = CrashRecord {
+        magic: 0,
+        panicked: 0,
+        mode_id: 0,
+    };
+
+    /// Records a crash while the given mode was displayed.
+    pub const fn new(crash: Crash, mode: Mode) -> Self {
+        CrashRecord {
+            magic: MAGIC,
+            panicked: matches!(crash, Crash::Panic) as u8,
+            mode_id: mode.id(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResetCause {
+    /// Powering on, the reset button, or uploading a new firmware.
+    Normal,
+    /// The MCU has been reset after a crash, which happened while the given mode was displayed
+    /// if it is known.
+    Crash(Crash, Option<Mode>),
+}
+
+impl ResetCause {
+    /// Determines the cause of the last reset, given the flags of `MCUSR` and a function reading
+    /// the crash record left in memory.
+    ///
+    /// The bootloader of the Arduino clears `MCUSR` before starting the firmware, so the flags
+    /// might all be unset. The crash record is used in that case.
+    pub fn new(mcusr: u8, read_record: impl FnOnce() -> CrashRecord) -> Self {
+        // The memory has never been written after powering on, so the record must not be read.
+        if mcusr & (POWER_ON_RESET | BROWN_OUT_RESET) != 0 {
+            return ResetCause::Normal;
+        }
+
+        let record = read_record();
+        if record.magic == MAGIC {
+            let crash = if record.panicked != 0 {
+                Crash::Panic
+            } else {
+                Crash::Watchdog
+            };
+            ResetCause::Crash(crash, Mode::from_id(record.mode_id))
+        } else if mcusr & WATCHDOG_RESET != 0 {
+            ResetCause::Crash(Crash::Watchdog, None)
+        } else {
+            ResetCause::Normal
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::MODES;
+
+    #[test]
+    fn reset_causes() {
+        // The record isn't read after powering on.
+        for mcusr in [
+            POWER_ON_RESET,
+            BROWN_OUT_RESET,
+            POWER_ON_RESET | WATCHDOG_RESET,
+        ] {
+            assert_eq!(
+                ResetCause::new(mcusr, || unreachable!()),
+                ResetCause::Normal
+            );
+        }
+
+        let record = CrashRecord::new(Crash::Panic, Mode::Fire);
+        assert_eq!(
+            ResetCause::new(WATCHDOG_RESET, || record),
+            ResetCause::Crash(Crash::Panic, Some(Mode::Fire))
+        );
+        // Flags cleared by the bootloader.
+        assert_eq!(
+            ResetCause::new(0, || record),
+            ResetCause::Crash(Crash::Panic, Some(Mode::Fire))
+        );
+        assert_eq!(
+            ResetCause::new(0, || CrashRecord::new(Crash::Watchdog, Mode::Rainbow)),
+            ResetCause::Crash(Crash::Watchdog, Some(Mode::Rainbow))
+        );
+
+        assert_eq!(
+            ResetCause::new(WATCHDOG_RESET, || CrashRecord::NONE),
+            ResetCause::Crash(Crash::Watchdog, None)
+        );
+        assert_eq!(ResetCause::new(0, || CrashRecord::NONE), ResetCause::Normal);
+    }
+
+    #[test]
+    fn garbage_records() {
+        let garbage = CrashRecord {
+            magic: 0x1234_5678,
+            panicked: 0xff,
+            mode_id: 0xff,
+        };
+        assert_eq!(ResetCause::new(0, || garbage), ResetCause::Normal);
+
+        // A mode that doesn't exist, for example after uploading an older firmware.
+        let unknown_mode = CrashRecord {
+            magic: MAGIC,
+            panicked: 0xff,
+            mode_id: 0xff,
+        };
+        assert_eq!(
+            ResetCause::new(0, || unknown_mode),
+            ResetCause::Crash(Crash::Panic, None)
+        );
+    }
+
+    #[test]
+    fn recorded_modes() {
+        for info in MODES.iter() {
+            assert_eq!(
+                ResetCause::new(0, || CrashRecord::new(Crash::Watchdog, info.mode)),
+                ResetCause::Crash(Crash::Watchdog, Some(info.mode))
+            );
+        }
+        assert_ne!(SAFE_MODE, Mode::Off);
+    }
+}
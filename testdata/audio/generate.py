@@ -0,0 +1,40 @@
+#!/usr/bin/env python3
+# Generates the sounds used by the tests of the `audio` module: 8-bit mono WAV files at the sample
+# rate of the microphone, 9.6kHz. The signal is centered and scaled as the microphone module does
+# for moderately loud sounds, with some noise.
+
+import math
+import random
+import wave
+
+RATE = 9600
+DURATION = 2.0
+
+
+def write(name, signal):
+    rng = random.Random(name)
+    frames = bytearray()
+    for n in range(int(RATE * DURATION)):
+        value = signal(n / RATE) + rng.gauss(0, 0.5)
+        frames.append(max(0, min(255, round(128 + value))))
+    with wave.open(name, "wb") as out:
+        out.setnchannels(1)
+        out.setsampwidth(1)
+        out.setframerate(RATE)
+        out.writeframes(bytes(frames))
+
+
+def kick(t):
+    # A kick drum every half second: a 60Hz sine quickly decaying.
+    t %= 0.5
+    return 80 * math.exp(-t * 20) * math.sin(2 * math.pi * 60 * t)
+
+
+def tone(frequency):
+    return lambda t: 40 * math.sin(2 * math.pi * frequency * t)
+
+
+write("silence.wav", lambda t: 0)
+write("kicks.wav", kick)
+write("tone_500hz.wav", tone(500))
+write("tone_3khz.wav", tone(3000))
@@ -0,0 +1,45 @@
+#!/usr/bin/env python3
+# Generates the pulse trains used by the tests of the `ir` module, for all the keys of the common
+# 24 keys remotes sold together with RGB LED strips, followed by a repeat code.
+#
+# They aren't recorded from a remote, but built from the codes these remotes are documented to
+# send, as printed by the IRremote library: 0xF700FF, 0xF7807F, 0xF740BF and so on, with the
+# bits in the order they are received. The durations are those of the output of a VS1838B
+# receiver, which stretches marks by about 60µs and shortens spaces by as much, with some jitter.
+# A capture from the actual remote should replace them when one is available.
+#
+# Each line is the name of the key, then the durations in microseconds of the marks (positive)
+# and the spaces (negative).
+
+import random
+
+# Third byte of the codes of each key, from left to right and top to bottom.
+KEYS = [
+    ("brightness_up", 0x00), ("brightness_down", 0x80), ("off", 0x40), ("on", 0xC0),
+    ("red", 0x20), ("green", 0xA0), ("blue", 0x60), ("white", 0xE0),
+    ("red_2", 0x10), ("green_2", 0x90), ("blue_2", 0x50), ("flash", 0xD0),
+    ("red_3", 0x30), ("green_3", 0xB0), ("blue_3", 0x70), ("strobe", 0xF0),
+    ("red_4", 0x08), ("green_4", 0x88), ("blue_4", 0x48), ("fade", 0xC8),
+    ("red_5", 0x28), ("green_5", 0xA8), ("blue_5", 0x68), ("smooth", 0xE8),
+]
+
+rng = random.Random(0)
+
+
+def pulse(mark, duration):
+    stretch = 60 if mark else -60
+    duration += stretch + round(rng.gauss(0, 20))
+    return duration if mark else -duration
+
+
+with open("remote_24_keys.txt", "w") as out:
+    for name, byte in KEYS:
+        code = 0x00F70000 | byte << 8 | (~byte & 0xFF)
+        pulses = [pulse(True, 9000), pulse(False, 4500)]
+        for bit in range(31, -1, -1):
+            pulses.append(pulse(True, 562))
+            pulses.append(pulse(False, 1687 if code >> bit & 1 else 562))
+        pulses.append(pulse(True, 562))
+        pulses.append(-40000)
+        pulses += [pulse(True, 9000), pulse(False, 2250), pulse(True, 562)]
+        out.write(name + " " + " ".join(f"{p:+d}" for p in pulses) + "\n")